@@ -4,17 +4,26 @@ extern crate rustc_serialize;
 extern crate star;
 
 use std::sync::{Arc, RwLock};
-use std::fs::File;
-use std::io::Read;
 
+use star::collect::discovery;
+use star::collect::discovery::kubernetes::KubernetesSource;
+use star::collect::discovery::marathon::MarathonSource;
 use star::collect::http::server;
 use star::collect::http::json::ResourcesSerializer;
-use star::collect::resource::{client, Resources, ResourceStore};
-use star::common::{self, logging, MS_PER_SEC};
+use star::collect::resource::{client, read_resources_file, watcher, Resources,
+    ResourceStore};
+use star::collect::resource::memory::MemoryResourceStore;
+use star::collect::resource::trace::TraceLog;
+#[cfg(feature = "sqlite")]
+use star::collect::resource::ResourcesPatch;
+#[cfg(feature = "sqlite")]
+use star::collect::resource::sqlite::SqliteResourceStore;
+use star::common::{self, logging, net, MS_PER_SEC};
+use star::common::dns::{self, Resolver};
+use star::common::net::Resolution;
 
 use docopt::Docopt;
 use jsonway::serializer::ObjectSerializer;
-use rustc_serialize::json;
 
 static USAGE: &'static str = "
 star-collect - Test program for network policies.
@@ -26,19 +35,58 @@ as well as modifying the set of target resources.
 
 Usage:
     star-collect --help
-    star-collect [--http-address=<address> --http-port=<port> --http-request-seconds=<seconds> --resources-file=<path> --logfile=<path>]
+    star-collect [--http-address=<address> --http-port=<port> --http-request-seconds=<seconds> --resources-file=<path> --resources-poll-seconds=<seconds> --marathon-url=<url> --marathon-app=<id> --marathon-path=<path> --kubernetes-url=<url> --kubernetes-namespace=<namespace> --kubernetes-selector=<selector> --kubernetes-port=<name> --kubernetes-path=<path> --kubernetes-token-file=<path> --discovery-seconds=<seconds> --dns-server=<address> --sqlite-store=<path> --trace-transitions --logfile=<path>]
 
 Options:
     --help                            Show this help message.
-    --http-address=<address>          Address to listen on for HTTP requests
-                                      [default: 0.0.0.0].
+    --http-address=<address>          List of comma-delimited addresses to
+                                      listen on for HTTP requests, e.g:
+                                      0.0.0.0,:: [default: 0.0.0.0].
     --http-port=<port>                Port to listen on for HTTP requests
                                       [default: 9001].
     --http-request-seconds=<seconds>  Seconds between resource fetch attempts
                                       [default: 5].
+    --discovery-seconds=<seconds>     Seconds between discovery source polls
+                                      [default: 5].
+    --dns-server=<address>            Nameserver to resolve the hosts of
+                                      resources and DNS discovery URLs with
+                                      instead of the system resolver, e.g:
+                                      10.0.0.2 or 10.0.0.2:5353.
+    --kubernetes-namespace=<namespace>
+                                      Kubernetes namespace of the discovered
+                                      Endpoints [default: default].
+    --kubernetes-path=<path>          Path to fetch from each discovered
+                                      Kubernetes endpoint [default: /status].
+    --kubernetes-port=<name>          Name of the Endpoints port to use.  The
+                                      first port is used if omitted.
+    --kubernetes-selector=<selector>  Label selector of the Endpoints whose
+                                      ready addresses are discovered as
+                                      resources, e.g: app=star-probe
+    --kubernetes-token-file=<path>    File containing a bearer token for the
+                                      Kubernetes API.
+    --kubernetes-url=<url>            Base URL of the Kubernetes API used to
+                                      discover resources, e.g:
+                                      https://kubernetes.default.svc
     --logfile=<path>                  File to log output to instead of stdout.
+    --marathon-app=<id>               Id of the Marathon app whose running
+                                      tasks are discovered as resources.
+    --marathon-path=<path>            Path to fetch from each discovered
+                                      Marathon task [default: /status].
+    --marathon-url=<url>              Base URL of the Marathon API used to
+                                      discover resources, e.g:
+                                      http://marathon.mesos:8080
     --resources-file=<path>           Path to file containing initial resources
-                                      as a JSON array.
+                                      as a JSON array.  The file is reloaded
+                                      when it changes or on SIGHUP.
+    --resources-poll-seconds=<seconds>
+                                      Seconds between checks of the resources
+                                      file for changes [default: 5].
+    --sqlite-store=<path>             Path to an SQLite database in which to
+                                      keep resources and responses instead of
+                                      memory.  Requires the sqlite feature.
+    --trace-transitions               Ask a probe to trace the path to each of
+                                      its targets that becomes degraded or
+                                      unreachable.  See GET /traces.
 ";
 
 fn main() {
@@ -50,19 +98,10 @@ fn main() {
     common::print_banner();
 
     // Read initial resources
-    let initial_resources: Resources = args.flag_resources_file.map(
-        |path| {
-            let mut file = File::open(&path).unwrap();
-            let mut raw = String::new();
-            file.read_to_string(&mut raw).unwrap();
-            let decode_result = json::decode(&raw);
-            match decode_result {
-                Ok(resources) => resources,
-                Err(cause) =>
-                    panic!("Failed to parse file [{}] as resources!\n{}",
-                           path,
-                           cause),
-            }
+    let initial_resources: Resources = args.flag_resources_file.as_ref().map(
+        |path| match read_resources_file(path) {
+            Ok(resources) => resources,
+            Err(cause) => panic!("{}", cause),
         }
     ).unwrap_or(vec!());
 
@@ -71,29 +110,142 @@ fn main() {
         .to_string());
 
     // Create the resource store
-    let resource_store = Arc::new(RwLock::new(
-        ResourceStore::new(initial_resources)));
+    let resource_store: Arc<RwLock<ResourceStore>> =
+        match args.flag_sqlite_store {
+            Some(path) => open_sqlite_store(&path, &initial_resources),
+            None => Arc::new(RwLock::new(
+                MemoryResourceStore::new(initial_resources.clone()))),
+        };
 
     // Create the resource client driver
     let http_req_ms =
         args.flag_http_request_seconds.parse::<u32>().unwrap() * MS_PER_SEC;
 
-    client::start_client_driver(http_req_ms as u64, resource_store.clone());
+    // Hosts are resolved via the nameserver if one is given
+    let nameserver = args.flag_dns_server.map(|address|
+        dns::parse_nameserver(&address)
+            .unwrap_or_else(|e| common::exit_with(e)));
+
+    // Discovery URLs are resolved via the nameserver, or else the system
+    // nameserver
+    let resolver = match nameserver {
+        Some(nameserver) => Some(Resolver::new(nameserver)),
+        None => match Resolver::system() {
+            Ok(resolver) => Some(resolver),
+            Err(cause) => {
+                println!("DNS discovery is disabled: {}", cause);
+                None
+            },
+        },
+    };
+
+    // Traces requested on target state transitions
+    let trace_log = Arc::new(RwLock::new(TraceLog::new()));
+
+    client::start_client_driver(http_req_ms as u64,
+                                resource_store.clone(),
+                                resolver,
+                                Resolution::with_nameserver(nameserver),
+                                if args.flag_trace_transitions {
+                                    Some(trace_log.clone())
+                                } else {
+                                    None
+                                });
+
+    // Watch the resources file for changes
+    if let Some(path) = args.flag_resources_file {
+        let reload_ms =
+            args.flag_resources_poll_seconds.parse::<u32>().unwrap() *
+                MS_PER_SEC;
+
+        watcher::start_file_watcher(path,
+                                    reload_ms as u64,
+                                    initial_resources.clone(),
+                                    resource_store.clone());
+    }
+
+    // Discover resources from Marathon
+    if let (Some(marathon_url), Some(app_id)) =
+            (args.flag_marathon_url, args.flag_marathon_app) {
+        let discovery_ms =
+            args.flag_discovery_seconds.parse::<u32>().unwrap() * MS_PER_SEC;
+
+        discovery::start_discovery_driver(
+            Box::new(MarathonSource::new(marathon_url,
+                                         app_id,
+                                         args.flag_marathon_path)),
+            discovery_ms as u64,
+            resource_store.clone());
+    }
+
+    // Discover resources from Kubernetes
+    if let (Some(kubernetes_url), Some(selector)) =
+            (args.flag_kubernetes_url, args.flag_kubernetes_selector) {
+        let discovery_ms =
+            args.flag_discovery_seconds.parse::<u32>().unwrap() * MS_PER_SEC;
+        let token = args.flag_kubernetes_token_file.map(|path|
+            KubernetesSource::read_token(&path)
+                .unwrap_or_else(|e| common::exit_with(e)));
+
+        discovery::start_discovery_driver(
+            Box::new(KubernetesSource::new(kubernetes_url,
+                                           args.flag_kubernetes_namespace,
+                                           selector,
+                                           args.flag_kubernetes_port,
+                                           args.flag_kubernetes_path,
+                                           token)),
+            discovery_ms as u64,
+            resource_store.clone());
+    }
 
     // Create the HTTP server
     server::start_server(
         resource_store.clone(),
-        args.flag_http_address,
+        trace_log,
+        net::parse_addresses(&args.flag_http_address),
         args.flag_http_port.parse().unwrap()
     );
 }
 
+#[cfg(feature = "sqlite")]
+fn open_sqlite_store(path: &str, initial_resources: &Resources)
+                     -> Arc<RwLock<ResourceStore>> {
+    let mut store = SqliteResourceStore::open(path)
+        .unwrap_or_else(|e| common::exit_with(e));
+    store.apply_patch(ResourcesPatch {
+        add: Some(initial_resources.clone()),
+        remove: None,
+    });
+    Arc::new(RwLock::new(store))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite_store(_: &str, _: &Resources) -> Arc<RwLock<ResourceStore>> {
+    common::exit_with(
+        "--sqlite-store requires star-collect to be built with the sqlite \
+         feature".to_string());
+}
+
 #[derive(Debug, RustcDecodable)]
 struct Args {
+    flag_discovery_seconds: String,
+    flag_dns_server: Option<String>,
     flag_help: bool,
     flag_http_address: String,
     flag_http_port: String,
     flag_http_request_seconds: String,
+    flag_kubernetes_namespace: String,
+    flag_kubernetes_path: String,
+    flag_kubernetes_port: Option<String>,
+    flag_kubernetes_selector: Option<String>,
+    flag_kubernetes_token_file: Option<String>,
+    flag_kubernetes_url: Option<String>,
     flag_logfile: Option<String>,
+    flag_marathon_app: Option<String>,
+    flag_marathon_path: String,
+    flag_marathon_url: Option<String>,
     flag_resources_file: Option<String>,
+    flag_resources_poll_seconds: String,
+    flag_sqlite_store: Option<String>,
+    flag_trace_transitions: bool,
 }
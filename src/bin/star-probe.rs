@@ -8,9 +8,14 @@ use std::sync::{Arc, RwLock};
 
 use star::common;
 use star::common::MS_PER_SEC;
+use star::common::dns::{self, Resolver};
+use star::common::identity::Identity;
 use star::common::logging;
+use star::common::net::{self, Source};
 use star::probe::http::server;
-use star::probe::status::{client, StatusCache};
+use star::probe::status::StatusCache;
+use star::probe::status::client::ProberBuilder;
+use star::probe::target::{read_targets_file, TargetSpec};
 
 use docopt::Docopt;
 
@@ -23,19 +28,50 @@ querying the most recent reachability data for its target set.
 
 Usage:
     star-probe --help
-    star-probe --urls=<urls> [--http-address=<address> --http-port=<port> --http-probe-seconds=<seconds> --logfile=<path>]
+    star-probe (--urls=<urls> | --targets-file=<path>) [--http-address=<address> --http-port=<port> --http-probe-seconds=<seconds> --attempts=<count> --attempt-interval-ms=<ms> --max-expansion=<count> --source-address=<address> --source-device=<device> --dns-server=<address> --id=<id> --logfile=<path>]
 
 Options:
     --help                          Show this help message.
-    --http-address=<address>        Address to listen on for HTTP requests
+    --http-address=<address>        List of comma-delimited addresses to listen
+                                    on for HTTP requests, e.g: 0.0.0.0,::
                                     [default: 0.0.0.0].
     --http-port=<port>              Port to listen on for HTTP requests
                                     [default: 9000].
     --http-probe-seconds=<seconds>  Seconds between probe connection attempts
                                     [default: 5].
+    --attempts=<count>              Probe attempts per target per round; a
+                                    target answering only some of them is
+                                    reported as degraded [default: 1].
+    --attempt-interval-ms=<ms>      Milliseconds between the attempts of a
+                                    round [default: 0].
     --urls=<urls>                   List of comma-delimited URLs to probe, e.g:
-                                    foo.baz.com:80,bar.baz.com:80
+                                    http://foo.baz.com:80,tcp://bar.baz.com:22
+                                    Supported schemes are http, https, tcp,
+                                    udp, icmp, star and mtu.
+                                    URLs prefixed with srv+ or dns+ are
+                                    expanded using DNS SRV or A records, e.g:
+                                    srv+http://_probe._tcp.star.mesos/status
+                                    URLs with an IPv4 CIDR block or a port
+                                    range are expanded into one target per
+                                    address and port, e.g:
+                                    tcp://10.0.3.0/28:8000-8010
+    --max-expansion=<count>         Maximum number of targets a single URL
+                                    may expand into [default: 1024].
+    --source-address=<address>      Local address to send probes from, on
+                                    hosts with several addresses.  Targets
+                                    may override it.
+    --source-device=<device>        Network device to send probes through,
+                                    e.g: eth1.  Requires CAP_NET_RAW.
+                                    Targets may override it.
+    --dns-server=<address>          Nameserver to resolve target hosts and
+                                    discovery URLs with instead of the system
+                                    resolver, e.g: 10.0.0.2 or 10.0.0.2:5353.
+                                    Targets may override it.
+    --id=<id>                       Identity reported to other probes by
+                                    GET /whoami.  Defaults to the hostname.
     --logfile=<path>                File to log output to instead of stdout.
+    --targets-file=<path>           Path to file containing targets as a JSON
+                                    array, with optional per-target settings.
 ";
 
 fn main() {
@@ -46,29 +82,73 @@ fn main() {
     logging::init_logger(args.flag_logfile).unwrap();
     common::print_banner();
 
-    let target_urls: Vec<String> = args.flag_urls
-        .split(",")
-        .map(|s| s.to_string())
-        .filter(|s| s != "")
-        .collect();
+    let targets: Vec<TargetSpec> = match args.flag_targets_file {
+        Some(path) => match read_targets_file(&path) {
+            Ok(targets) => targets,
+            Err(cause) => panic!("{}", cause),
+        },
+        None => args.flag_urls.unwrap_or(String::new())
+            .split(",")
+            .filter(|s| *s != "")
+            .map(|s| TargetSpec::new(s))
+            .collect(),
+    };
+    let target_urls: Vec<String> =
+        targets.iter().map(|t| t.url.clone()).collect();
 
     info!("Target URLs: {:?}", &target_urls);
 
     // Create the status cache
     let status_cache = Arc::new(RwLock::new(StatusCache::new(&target_urls)));
 
-    // Create the peer probe client driver
+    // Create the peer probe engine
     let http_probe_ms =
         args.flag_http_probe_seconds.parse::<u32>().unwrap() * MS_PER_SEC;
 
-    client::start_client_driver(target_urls,
-                              http_probe_ms as u64,
-                              status_cache.clone());
+    // Hosts are resolved via the nameserver if one is given
+    let nameserver = args.flag_dns_server.map(|address|
+        dns::parse_nameserver(&address)
+            .unwrap_or_else(|e| common::exit_with(e)));
+
+    // Discovery URLs are resolved via the nameserver, or else the system
+    // nameserver
+    let resolver = if let Some(nameserver) = nameserver {
+        Some(Resolver::new(nameserver))
+    } else if target_urls.iter().any(|u| dns::is_discovery_url(u)) {
+        Some(Resolver::system().unwrap_or_else(|e| common::exit_with(e)))
+    } else {
+        None
+    };
+
+    let source = Source {
+        address: args.flag_source_address,
+        device: args.flag_source_device,
+    };
+
+    let mut prober = ProberBuilder::new()
+        .target_specs(targets)
+        .interval_ms(http_probe_ms as u64)
+        .attempts(args.flag_attempts.parse().unwrap())
+        .attempt_interval_ms(args.flag_attempt_interval_ms.parse().unwrap())
+        .max_expansion(args.flag_max_expansion.parse().unwrap())
+        .source(source.clone())
+        .status_cache(status_cache.clone());
+    if let Some(resolver) = resolver {
+        prober = prober.resolver(resolver);
+    }
+    if let Some(nameserver) = nameserver {
+        prober = prober.nameserver(nameserver);
+    }
+    let mut prober = prober.build();
+    prober.start();
 
     // Create the HTTP server
     server::start_server(
         status_cache.clone(),
-        args.flag_http_address,
+        Identity::new(args.flag_id),
+        source,
+        nameserver,
+        net::parse_addresses(&args.flag_http_address),
         args.flag_http_port.parse().unwrap()
     );
 }
@@ -79,6 +159,14 @@ struct Args {
     flag_http_address: String,
     flag_http_port: String,
     flag_http_probe_seconds: String,
-    flag_urls: String,
+    flag_attempts: String,
+    flag_attempt_interval_ms: String,
+    flag_max_expansion: String,
+    flag_source_address: Option<String>,
+    flag_source_device: Option<String>,
+    flag_dns_server: Option<String>,
+    flag_id: Option<String>,
+    flag_targets_file: Option<String>,
+    flag_urls: Option<String>,
     flag_logfile: Option<String>,
 }
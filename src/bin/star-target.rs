@@ -0,0 +1,98 @@
+extern crate docopt;
+#[macro_use]
+extern crate log;
+extern crate rustc_serialize;
+extern crate star;
+
+use std::sync::{Arc, RwLock};
+
+use star::common;
+use star::common::identity::Identity;
+use star::common::logging;
+use star::common::net;
+use star::common::ports::parse_ports;
+use star::target::Listeners;
+use star::target::http::server;
+use star::target::responder;
+
+use docopt::Docopt;
+
+static USAGE: &'static str = "
+star-target - Test program for network policies.
+
+This program listens on each configured TCP and UDP port, answering
+connections and datagrams with its identity and echoing what it receives.
+It provides a REST API for querying its set of listeners.
+
+Usage:
+    star-target --help
+    star-target [--tcp=<ports> --udp=<ports> --address=<address> --http-address=<address> --http-port=<port> --id=<id> --logfile=<path>]
+
+Options:
+    --help                          Show this help message.
+    --tcp=<ports>                   List of comma-delimited TCP ports and port
+                                    ranges to listen on, e.g: 80,443,8000-8010
+    --udp=<ports>                   List of comma-delimited UDP ports and port
+                                    ranges to listen on, e.g: 53,5000-5010
+    --address=<address>             List of comma-delimited addresses to listen
+                                    on for TCP connections and UDP datagrams,
+                                    e.g: 0.0.0.0,:: [default: 0.0.0.0].
+    --http-address=<address>        List of comma-delimited addresses to listen
+                                    on for HTTP requests [default: 0.0.0.0].
+    --http-port=<port>              Port to listen on for HTTP requests
+                                    [default: 9002].
+    --id=<id>                       Identity reported to clients.  Defaults to
+                                    the hostname.
+    --logfile=<path>                File to log output to instead of stdout.
+";
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| e.exit());
+
+    logging::init_logger(args.flag_logfile).unwrap();
+    common::print_banner();
+
+    let tcp_ports = parse_ports(&args.flag_tcp.unwrap_or(String::new()))
+        .unwrap_or_else(|cause|
+            common::exit_with(format!("Invalid --tcp ports: {}", cause)));
+    let udp_ports = parse_ports(&args.flag_udp.unwrap_or(String::new()))
+        .unwrap_or_else(|cause|
+            common::exit_with(format!("Invalid --udp ports: {}", cause)));
+
+    info!("TCP ports: {:?}", &tcp_ports);
+    info!("UDP ports: {:?}", &udp_ports);
+
+    let identity = Identity::new(args.flag_id);
+
+    // Start the responders
+    let listeners = Arc::new(RwLock::new(Listeners::new()));
+    responder::start_responders(
+        identity.clone(),
+        &net::parse_addresses(&args.flag_address),
+        &tcp_ports,
+        &udp_ports,
+        listeners.clone()
+    ).unwrap_or_else(|e| common::exit_with(e));
+
+    // Create the HTTP server
+    server::start_server(
+        listeners.clone(),
+        identity,
+        net::parse_addresses(&args.flag_http_address),
+        args.flag_http_port.parse().unwrap()
+    );
+}
+
+#[derive(Debug, RustcDecodable)]
+struct Args {
+    flag_help: bool,
+    flag_tcp: Option<String>,
+    flag_udp: Option<String>,
+    flag_address: String,
+    flag_http_address: String,
+    flag_http_port: String,
+    flag_id: Option<String>,
+    flag_logfile: Option<String>,
+}
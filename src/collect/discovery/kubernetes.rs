@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::thread;
+
+use collect::discovery::{fetch_json, DiscoverySource};
+use collect::resource::{Labels, Resource, Resources};
+use common::http::Connector;
+use common::net::{bracket_host, Resolution, Source};
+
+use hyper::Client;
+use hyper::header::{Connection, Headers};
+use hyper::status::StatusCode;
+use rustc_serialize::json::Json;
+use url::percent_encoding::{utf8_percent_encode, QUERY_ENCODE_SET};
+
+const WATCH_RETRY_MS: u32 = 1000;
+
+/// Discovers the ready addresses of the Endpoints objects that match a
+/// label selector, via a Kubernetes-compatible API server.
+///
+/// Each ready address becomes a resource whose id is `<namespace>/<pod>`
+/// (or `<namespace>/<ip>:<port>` for addresses without a pod) and whose URL
+/// is `http://<ip>:<port><path>`.  The labels of the backing pod are carried
+/// over as resource labels, along with a `kubernetes-service` label naming
+/// the Endpoints object.
+#[derive(Clone)]
+pub struct KubernetesSource {
+    api_url: String,
+    namespace: String,
+    selector: String,
+    port_name: Option<String>,
+    path: String,
+    token: Option<String>,
+}
+
+impl KubernetesSource {
+    pub fn new(api_url: String,
+               namespace: String,
+               selector: String,
+               port_name: Option<String>,
+               path: String,
+               token: Option<String>) -> KubernetesSource {
+        KubernetesSource {
+            api_url: api_url.trim_right_matches('/').to_string(),
+            namespace: namespace,
+            selector: selector,
+            port_name: port_name,
+            path: path,
+            token: token,
+        }
+    }
+
+    /// Reads a bearer token, such as a service account token, from a file.
+    pub fn read_token(path: &str) -> Result<String, String> {
+        let mut token = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut token))
+            .map_err(|e| format!("Failed to read token [{}]: {}", path, e)));
+        Ok(token.trim().to_string())
+    }
+
+    fn collection_url(&self, kind: &str, watch: bool) -> String {
+        format!("{}/api/v1/namespaces/{}/{}?labelSelector={}{}",
+                self.api_url,
+                self.namespace,
+                kind,
+                utf8_percent_encode(&self.selector, QUERY_ENCODE_SET),
+                if watch { "&watch=true" } else { "" })
+    }
+
+    fn headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        if let Some(ref token) = self.token {
+            headers.set_raw("Authorization",
+                            vec!(format!("Bearer {}", token).into_bytes()));
+        }
+        headers
+    }
+
+    /// Returns the labels of every pod in the namespace, by pod name.
+    fn pod_labels(&self) -> Result<HashMap<String, Labels>, String> {
+        let pods_url = format!("{}/api/v1/namespaces/{}/pods",
+                               self.api_url,
+                               self.namespace);
+        let json = try!(fetch_json(&pods_url, self.headers()));
+        match json.find("items").and_then(|i| i.as_array()) {
+            Some(pods) => Ok(pod_labels(pods)),
+            None => Err(format!("Response from [{}] has no items", pods_url)),
+        }
+    }
+
+    /// Converts the ready addresses of Endpoints objects to resources,
+    /// labeled with the labels of their pods.
+    fn resources(&self,
+                 items: &[Json],
+                 pod_labels: &HashMap<String, Labels>) -> Resources {
+
+        let mut resources = vec!();
+        for endpoints in items {
+            let service = endpoints.find_path(&["metadata", "name"])
+                .and_then(|n| n.as_string())
+                .unwrap_or("");
+            let subsets = endpoints.find("subsets")
+                .and_then(|s| s.as_array())
+                .map(|s| &s[..])
+                .unwrap_or(&[]);
+
+            for subset in subsets {
+                let port = match self.select_port(subset) {
+                    Some(port) => port,
+                    None => continue,
+                };
+                let addresses = subset.find("addresses")
+                    .and_then(|a| a.as_array())
+                    .map(|a| &a[..])
+                    .unwrap_or(&[]);
+
+                for address in addresses {
+                    let ip = match address.find("ip")
+                        .and_then(|i| i.as_string()) {
+                        Some(ip) => ip,
+                        None => continue,
+                    };
+                    let pod = address.find_path(&["targetRef", "name"])
+                        .and_then(|n| n.as_string());
+
+                    let mut labels = pod
+                        .and_then(|pod| pod_labels.get(pod))
+                        .cloned()
+                        .unwrap_or(Labels::new());
+                    labels.insert("kubernetes-service".to_string(),
+                                  service.to_string());
+
+                    let id = match pod {
+                        Some(pod) => format!("{}/{}", self.namespace, pod),
+                        None => format!("{}/{}:{}", self.namespace, ip, port),
+                    };
+                    resources.push(Resource {
+                        id: id,
+                        url: format!("http://{}:{}{}",
+                                     bracket_host(ip), port, self.path),
+                        labels: labels,
+                    });
+                }
+            }
+        }
+        resources
+    }
+
+    /// Selects the named port of the subset, or its first port if no port
+    /// name was configured.
+    fn select_port(&self, subset: &Json) -> Option<u64> {
+        let ports = match subset.find("ports").and_then(|p| p.as_array()) {
+            Some(ports) => ports,
+            None => return None,
+        };
+        ports.iter()
+            .find(|port| match self.port_name {
+                Some(ref name) =>
+                    port.find("name").and_then(|n| n.as_string()) ==
+                        Some(&name[..]),
+                None => true,
+            })
+            .and_then(|port| port.find("port"))
+            .and_then(|port| port.as_u64())
+    }
+
+    /// Streams watch events for the matching Endpoints, invoking `changed`
+    /// for each one.  Reconnects whenever the stream ends.
+    fn watch_endpoints(&self, changed: Box<Fn() + Send>) {
+        let watch_url = self.collection_url("endpoints", true);
+        loop {
+            // Watch events may be far apart, so only connecting times out.
+            let client = Client::with_connector(
+                Connector::new(None, Source::default(), Resolution::default())
+                    .read_timeout(None));
+            let response = client.get(&watch_url)
+                .headers(self.headers())
+                .header(Connection::close())
+                .send();
+
+            match response {
+                Ok(ref response) if response.status != StatusCode::Ok =>
+                    warn!("Watching [{}] returned [{}]",
+                          watch_url,
+                          response.status),
+                Ok(response) => {
+                    // Each watch event is a single line of JSON.
+                    for line in BufReader::new(response).lines() {
+                        match line {
+                            Ok(ref line) if line.trim().is_empty() => (),
+                            Ok(_) => changed(),
+                            Err(_) => break,
+                        }
+                    }
+                },
+                Err(cause) => warn!("Failed to watch [{}]: {}",
+                                    watch_url,
+                                    cause),
+            }
+            thread::sleep_ms(WATCH_RETRY_MS);
+        }
+    }
+}
+
+impl DiscoverySource for KubernetesSource {
+    fn name(&self) -> String {
+        format!("kubernetes endpoints {}/{}", self.namespace, self.selector)
+    }
+
+    fn discover(&mut self) -> Result<Resources, String> {
+        let endpoints_url = self.collection_url("endpoints", false);
+        let json = try!(fetch_json(&endpoints_url, self.headers()));
+        let items = match json.find("items").and_then(|i| i.as_array()) {
+            Some(items) => items,
+            None => return Err(
+                format!("Response from [{}] has no items", endpoints_url)),
+        };
+        let pod_labels = try!(self.pod_labels());
+        Ok(self.resources(items, &pod_labels))
+    }
+
+    fn watch(&mut self, changed: Box<Fn() + Send>) {
+        let source = self.clone();
+        thread::spawn(move || source.watch_endpoints(changed));
+    }
+}
+
+/// The labels of each pod, by pod name.
+fn pod_labels(pods: &[Json]) -> HashMap<String, Labels> {
+    let mut result = HashMap::new();
+    for pod in pods {
+        let name = pod.find_path(&["metadata", "name"])
+            .and_then(|n| n.as_string());
+        if let Some(name) = name {
+            result.insert(name.to_string(), json_labels(pod));
+        }
+    }
+    result
+}
+
+fn json_labels(object: &Json) -> Labels {
+    let mut labels = Labels::new();
+    if let Some(json) = object.find_path(&["metadata", "labels"])
+        .and_then(|l| l.as_object()) {
+        for (key, value) in json {
+            if let Some(value) = value.as_string() {
+                labels.insert(key.clone(), value.to_string());
+            }
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use collect::discovery::{stub, DiscoverySource};
+    use collect::resource::{Labels, Resource};
+
+    use rustc_serialize::json::Json;
+
+    use super::{pod_labels, KubernetesSource};
+
+    static ENDPOINTS: &'static str = r#"{"items": [
+        {"metadata": {"name": "web"},
+         "subsets": [
+            {"addresses": [
+                {"ip": "10.0.0.1",
+                 "targetRef": {"kind": "Pod", "name": "web-1"}},
+                {"ip": "fd00::2"}],
+             "ports": [{"name": "metrics", "port": 9100},
+                       {"name": "star", "port": 9000}]},
+            {"addresses": [{"ip": "10.0.0.3"}],
+             "ports": [{"name": "metrics", "port": 9100}]}]},
+        {"metadata": {"name": "empty"}}
+    ]}"#;
+
+    static PODS: &'static str = r#"{"items": [
+        {"metadata": {"name": "web-1",
+                      "labels": {"app": "web", "zone": "a"}}},
+        {"metadata": {"labels": {"app": "nameless"}}}
+    ]}"#;
+
+    fn source(api_url: &str, port_name: Option<&str>) -> KubernetesSource {
+        KubernetesSource::new(api_url.to_string(),
+                              "star".to_string(),
+                              "app=web".to_string(),
+                              port_name.map(|name| name.to_string()),
+                              "/status".to_string(),
+                              Some("secret".to_string()))
+    }
+
+    fn resource(id: &str, url: &str, pairs: &[(&str, &str)]) -> Resource {
+        let mut labels: Labels = pairs.iter()
+            .map(|&(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        labels.insert("kubernetes-service".to_string(), "web".to_string());
+        Resource { id: id.to_string(), url: url.to_string(), labels: labels }
+    }
+
+    fn items(raw: &str) -> Vec<Json> {
+        Json::from_str(raw).unwrap()
+            .find("items").unwrap()
+            .as_array().unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn labels_by_pod() {
+        let labels = pod_labels(&items(PODS));
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels["web-1"].get("zone"), Some(&"a".to_string()));
+    }
+
+    #[test]
+    fn named_port_addresses_become_resources() {
+        let pods = pod_labels(&items(PODS));
+        let resources = source("http://api", Some("star"))
+            .resources(&items(ENDPOINTS), &pods);
+        assert_eq!(resources, vec!(
+            resource("star/web-1",
+                     "http://10.0.0.1:9000/status",
+                     &[("app", "web"), ("zone", "a")]),
+            resource("star/fd00::2:9000",
+                     "http://[fd00::2]:9000/status",
+                     &[])));
+    }
+
+    #[test]
+    fn first_port_is_the_default() {
+        let pods = pod_labels(&items(PODS));
+        let resources = source("http://api", None)
+            .resources(&items(ENDPOINTS), &pods);
+        let urls: Vec<&str> = resources.iter()
+            .map(|resource| &resource.url[..])
+            .collect();
+        assert_eq!(urls, vec!("http://10.0.0.1:9100/status",
+                              "http://[fd00::2]:9100/status",
+                              "http://10.0.0.3:9100/status"));
+    }
+
+    #[test]
+    fn discover_from_stub_api_server() {
+        let server = stub::start(vec!(
+            ("/api/v1/namespaces/star/endpoints", ENDPOINTS.to_string()),
+            ("/api/v1/namespaces/star/pods", PODS.to_string())));
+
+        let resources = source(&server.url, Some("star")).discover().unwrap();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[0].labels.get("app"), Some(&"web".to_string()));
+
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].starts_with(
+            "GET /api/v1/namespaces/star/endpoints?labelSelector=app=web "));
+        assert!(requests.iter()
+            .all(|request| request.contains("Authorization: Bearer secret")));
+    }
+
+    #[test]
+    fn discover_from_missing_namespace() {
+        let server = stub::start(vec!());
+        assert!(source(&server.url, None).discover().is_err());
+    }
+}
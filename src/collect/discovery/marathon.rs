@@ -0,0 +1,153 @@
+use collect::discovery::{fetch_json, DiscoverySource};
+use collect::resource::{Labels, Resource, Resources};
+use common::net::bracket_host;
+
+use hyper::header::Headers;
+use rustc_serialize::json::Json;
+
+/// Discovers the running tasks of a Marathon app via its
+/// `/v2/apps/{id}/tasks` endpoint.  Each task becomes a resource whose id
+/// is the task id and whose URL is `http://<host>:<port><path>`, where the
+/// port is the task's first allocated port.
+pub struct MarathonSource {
+    marathon_url: String,
+    app_id: String,
+    path: String,
+}
+
+impl MarathonSource {
+    pub fn new(marathon_url: String, app_id: String, path: String)
+               -> MarathonSource {
+        MarathonSource {
+            marathon_url: marathon_url.trim_right_matches('/').to_string(),
+            app_id: app_id.trim_matches('/').to_string(),
+            path: path,
+        }
+    }
+
+    fn tasks_url(&self) -> String {
+        format!("{}/v2/apps/{}/tasks", self.marathon_url, self.app_id)
+    }
+
+    /// Converts the running tasks listed in a tasks response to resources.
+    fn resources(&self, json: &Json) -> Result<Resources, String> {
+        let tasks = match json.find("tasks").and_then(|t| t.as_array()) {
+            Some(tasks) => tasks,
+            None => return Err(format!("Response from [{}] has no tasks",
+                                       self.tasks_url())),
+        };
+
+        let mut labels = Labels::new();
+        labels.insert("marathon-app".to_string(), format!("/{}", self.app_id));
+
+        Ok(tasks.iter()
+            .filter(|task| is_running(task))
+            .filter_map(|task| {
+                let id = task.find("id").and_then(|i| i.as_string());
+                let host = task.find("host").and_then(|h| h.as_string());
+                let port = task.find("ports")
+                    .and_then(|p| p.as_array())
+                    .and_then(|p| p.first())
+                    .and_then(|p| p.as_u64());
+                match (id, host, port) {
+                    (Some(id), Some(host), Some(port)) => Some(Resource {
+                        id: id.to_string(),
+                        url: format!("http://{}:{}{}",
+                                     bracket_host(host), port, self.path),
+                        labels: labels.clone(),
+                    }),
+                    _ => None,
+                }
+            })
+            .collect())
+    }
+}
+
+impl DiscoverySource for MarathonSource {
+    fn name(&self) -> String {
+        format!("marathon app /{}", self.app_id)
+    }
+
+    fn discover(&mut self) -> Result<Resources, String> {
+        let json = try!(fetch_json(&self.tasks_url(), Headers::new()));
+        self.resources(&json)
+    }
+}
+
+/// Newer versions of Marathon report the task state; older versions only
+/// set `startedAt` once the task is running.
+fn is_running(task: &Json) -> bool {
+    match task.find("state").and_then(|s| s.as_string()) {
+        Some(state) => state == "TASK_RUNNING",
+        None => task.find("startedAt").map(|s| s.is_string()).unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use collect::discovery::{stub, DiscoverySource};
+    use collect::resource::{Labels, Resource};
+
+    use rustc_serialize::json::Json;
+
+    use super::MarathonSource;
+
+    static TASKS: &'static str = r#"{"tasks": [
+        {"id": "web.1", "host": "10.0.0.1", "ports": [31001, 31002],
+         "state": "TASK_RUNNING"},
+        {"id": "web.2", "host": "fd00::2", "ports": [31003],
+         "startedAt": "2015-08-01T00:00:00.000Z"},
+        {"id": "web.3", "host": "10.0.0.3", "ports": [31004],
+         "state": "TASK_STAGING"},
+        {"id": "web.4", "host": "10.0.0.4", "ports": [],
+         "state": "TASK_RUNNING"},
+        {"id": "web.5", "host": "10.0.0.5", "ports": [31005],
+         "startedAt": null}
+    ]}"#;
+
+    fn resource(id: &str, url: &str) -> Resource {
+        let mut labels = Labels::new();
+        labels.insert("marathon-app".to_string(), "/star/web".to_string());
+        Resource { id: id.to_string(), url: url.to_string(), labels: labels }
+    }
+
+    #[test]
+    fn running_tasks_become_resources() {
+        let source = MarathonSource::new("http://marathon:8080/".to_string(),
+                                         "/star/web/".to_string(),
+                                         "/status".to_string());
+        let resources = source.resources(&Json::from_str(TASKS).unwrap());
+        assert_eq!(resources, Ok(vec!(
+            resource("web.1", "http://10.0.0.1:31001/status"),
+            resource("web.2", "http://[fd00::2]:31003/status"))));
+    }
+
+    #[test]
+    fn missing_tasks_are_an_error() {
+        let source = MarathonSource::new("http://marathon:8080".to_string(),
+                                         "star/web".to_string(),
+                                         "/status".to_string());
+        let error = source.resources(&Json::from_str("{}").unwrap()).err();
+        assert_eq!(error, Some("Response from \
+                                [http://marathon:8080/v2/apps/star/web/tasks] \
+                                has no tasks".to_string()));
+    }
+
+    #[test]
+    fn discover_from_stub_marathon() {
+        let server = stub::start(vec!(
+            ("/v2/apps/star/web/tasks", TASKS.to_string())));
+
+        let mut source = MarathonSource::new(server.url.clone(),
+                                             "/star/web".to_string(),
+                                             "/status".to_string());
+        let resources = source.discover().unwrap();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[0].id, "web.1");
+
+        let mut missing = MarathonSource::new(server.url.clone(),
+                                              "/star/missing".to_string(),
+                                              "/status".to_string());
+        assert!(missing.discover().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::io::Read;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use collect::resource::{Resources, ResourcesPatch, ResourceStore};
+use common::http;
+
+use hyper::header::{Accept, Connection, Headers};
+use hyper::status::StatusCode;
+use mio::{EventLoop, Handler};
+use rustc_serialize::json::Json;
+
+pub mod kubernetes;
+pub mod marathon;
+
+#[cfg(test)]
+mod stub;
+
+/// A source of resources that are kept in sync with an external system.
+pub trait DiscoverySource: Send {
+    /// A short description of the source, for logging.
+    fn name(&self) -> String;
+
+    /// Returns the complete set of resources currently known to the source.
+    fn discover(&mut self) -> Result<Resources, String>;
+
+    /// Starts watching the source for changes, invoking `changed` whenever
+    /// the set of resources may have changed so that it is rediscovered
+    /// without waiting for the next poll.  By default sources are only
+    /// polled.
+    fn watch(&mut self, _changed: Box<Fn() + Send>) {}
+}
+
+/// Periodically polls the discovery source and reconciles the resource
+/// store with its results.  Only resources that were previously discovered
+/// from this source are removed when they disappear; if the source fails,
+/// the previously discovered resources are kept.
+pub fn start_discovery_driver(source: Box<DiscoverySource>,
+                              discovery_ms: u64,
+                              resource_store: Arc<RwLock<ResourceStore>>) {
+    info!("Starting discovery driver for [{}]", source.name());
+    let mut event_loop = EventLoop::new().unwrap();
+    let _ = event_loop.timeout_ms((), 0);
+
+    let mut source = source;
+    let loop_channel = event_loop.channel();
+    source.watch(Box::new(move || { let _ = loop_channel.send(()); }));
+
+    thread::spawn(move || {
+        let _ = event_loop.run(&mut DiscoveryHandler {
+            source: source,
+            discovery_ms: discovery_ms,
+            discovered: vec!(),
+            resource_store: resource_store,
+        });
+    });
+}
+
+struct DiscoveryHandler {
+    source: Box<DiscoverySource>,
+    discovery_ms: u64,
+    discovered: Resources,
+    resource_store: Arc<RwLock<ResourceStore>>,
+}
+
+impl DiscoveryHandler {
+    fn discover(&mut self) {
+        match self.source.discover() {
+            Ok(resources) => {
+                sync_resources(&self.resource_store,
+                               &self.discovered,
+                               &resources);
+                self.discovered = resources;
+            },
+            Err(cause) => warn!("Discovery from [{}] failed: {}",
+                                self.source.name(),
+                                cause),
+        }
+    }
+}
+
+impl Handler for DiscoveryHandler {
+    type Timeout = ();
+    type Message = ();
+
+    fn timeout(&mut self,
+               event_loop: &mut EventLoop<DiscoveryHandler>,
+               _: ()) {
+        self.discover();
+        let _ = event_loop.timeout_ms((), self.discovery_ms);
+    }
+
+    fn notify(&mut self,
+              _: &mut EventLoop<DiscoveryHandler>,
+              _: ()) {
+        info!("Discovery source [{}] changed", self.source.name());
+        self.discover();
+    }
+}
+
+/// Applies the difference between the previous and current results of a
+/// discovery source to the resource store.
+pub fn sync_resources(resource_store: &Arc<RwLock<ResourceStore>>,
+                      previous: &Resources,
+                      current: &Resources) {
+    let removed: Vec<String> = previous.iter()
+        .filter(|old| !current.iter().any(|new| new.id == old.id))
+        .map(|old| old.id.clone())
+        .collect();
+
+    if !removed.is_empty() {
+        info!("Removing undiscovered resources {:?}", removed);
+    }
+
+    resource_store.write().unwrap().apply_patch(ResourcesPatch {
+        add: Some(current.clone()),
+        remove: Some(removed),
+    });
+}
+
+/// Fetches the URL and parses the response body as JSON.  The `Accept`
+/// header is set in addition to the supplied headers.
+pub fn fetch_json(url: &str, headers: Headers) -> Result<Json, String> {
+    let client = http::client(None);
+    let mut response = try!(client.get(url)
+        .headers(headers)
+        .header(Accept::json())
+        .header(Connection::close())
+        .send()
+        .map_err(|e| format!("Failed to fetch [{}]: {}", url, e)));
+
+    if response.status != StatusCode::Ok {
+        return Err(format!("Fetching [{}] returned [{}]",
+                           url,
+                           response.status));
+    }
+
+    let mut body = String::new();
+    try!(response.read_to_string(&mut body)
+        .map_err(|e| format!("Failed to read [{}]: {}", url, e)));
+    Json::from_str(&body)
+        .map_err(|e| format!("Failed to parse [{}]: {}", url, e))
+}
@@ -0,0 +1,66 @@
+//! A minimal HTTP server on the loopback interface, used to test discovery
+//! sources against canned API responses.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A running stub server.
+pub struct StubServer {
+    /// The base URL of the server, e.g. `http://127.0.0.1:40000`.
+    pub url: String,
+    /// The head (request line and headers) of every request received.
+    pub requests: Arc<Mutex<Vec<String>>>,
+}
+
+/// Starts a server that answers requests for each path, ignoring the
+/// query, with the JSON body configured for it, and any other request
+/// with `404 Not Found`.
+pub fn start(routes: Vec<(&'static str, String)>) -> StubServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(vec!()));
+
+    let received = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                serve(stream, &routes, &received);
+            }
+        }
+    });
+    StubServer { url: url, requests: requests }
+}
+
+fn serve(mut stream: TcpStream,
+         routes: &[(&'static str, String)],
+         received: &Arc<Mutex<Vec<String>>>) {
+    let mut head = vec!();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(1) => head.push(byte[0]),
+            _ => return,
+        }
+    }
+    let head = String::from_utf8_lossy(&head).into_owned();
+    let target = head.split_whitespace().nth(1).unwrap_or("").to_string();
+    let path = target.split('?').next().unwrap_or("");
+    received.lock().unwrap().push(head.clone());
+
+    let response = match routes.iter().find(|route| route.0 == path) {
+        Some(&(_, ref body)) =>
+            format!("HTTP/1.1 200 OK\r\n\
+                     Content-Type: application/json\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    body.len(),
+                    body),
+        None =>
+            "HTTP/1.1 404 Not Found\r\n\
+             Content-Length: 0\r\n\
+             Connection: close\r\n\r\n".to_string(),
+    };
+    let _ = stream.write_all(response.as_bytes());
+}
@@ -1,4 +1,7 @@
 use collect::resource::{Resource, Resources, Response, Responses};
+use collect::resource::group::{GroupEdge, GroupSummary};
+use collect::resource::rewrite::Rewrite;
+use collect::resource::trace::TraceRecord;
 
 use jsonway::{ArrayBuilder, ObjectBuilder, ObjectSerializer};
 use rustc_serialize::json::Json;
@@ -10,6 +13,7 @@ impl ObjectSerializer<Resource> for ResourceSerializer {
     fn build(&self, resource: &Resource, json: &mut ObjectBuilder) {
         json.set("id", resource.id.clone());
         json.set("url", resource.url.clone());
+        json.set("labels", resource.labels.clone());
     }
 }
 
@@ -52,3 +56,79 @@ impl ObjectSerializer<Responses> for ResponsesSerializer {
         }
     }
 }
+
+pub struct GroupEdgeSerializer;
+
+impl ObjectSerializer<GroupEdge> for GroupEdgeSerializer {
+    fn root(&self) -> Option<&str> { None }
+    fn build(&self, edge: &GroupEdge, json: &mut ObjectBuilder) {
+        json.set("from", edge.from.clone());
+        json.set("to", edge.to.clone());
+        json.set("reachable", edge.reachable);
+        json.set("degraded", edge.degraded);
+        json.set("unreachable", edge.unreachable);
+    }
+}
+
+pub struct GroupSummarySerializer;
+
+impl ObjectSerializer<GroupSummary> for GroupSummarySerializer {
+    fn root(&self) -> Option<&str> { Some("groups") }
+    fn build(&self, summary: &GroupSummary, json: &mut ObjectBuilder) {
+        let edges_json = summary.edges.iter().map(|e|
+            GroupEdgeSerializer.serialize(e, false)).collect::<Vec<_>>();
+
+        json.set("label", summary.label.clone());
+        json.set("edges", edges_json);
+    }
+}
+
+pub struct RewriteSerializer;
+
+impl ObjectSerializer<Rewrite> for RewriteSerializer {
+    fn root(&self) -> Option<&str> { None }
+    fn build(&self, rewrite: &Rewrite, json: &mut ObjectBuilder) {
+        json.set("id", rewrite.id.clone());
+        json.set("sourceAddress", rewrite.source_address.clone());
+        json.set("targetUrl", rewrite.target_url.clone());
+        json.set("observedSourceAddress", rewrite.observed_address.clone());
+    }
+}
+
+pub struct RewritesSerializer;
+
+impl ObjectSerializer<Vec<Rewrite>> for RewritesSerializer {
+    fn root(&self) -> Option<&str> { None }
+    fn build(&self, rewrites: &Vec<Rewrite>, json: &mut ObjectBuilder) {
+        let rewrites_json = rewrites.iter().map(|r|
+            RewriteSerializer.serialize(r, false)).collect::<Vec<_>>();
+
+        json.set("rewrites", rewrites_json);
+    }
+}
+
+pub struct TraceRecordSerializer;
+
+impl ObjectSerializer<TraceRecord> for TraceRecordSerializer {
+    fn root(&self) -> Option<&str> { None }
+    fn build(&self, record: &TraceRecord, json: &mut ObjectBuilder) {
+        json.set("id", record.id.clone());
+        json.set("targetUrl", record.target_url.clone());
+        json.set("reachability", record.reachability.clone());
+        json.set("requestedAt", record.requested_at);
+        json.set("trace", record.trace.clone());
+        json.set("error", record.error.clone());
+    }
+}
+
+pub struct TracesSerializer;
+
+impl ObjectSerializer<Vec<TraceRecord>> for TracesSerializer {
+    fn root(&self) -> Option<&str> { None }
+    fn build(&self, records: &Vec<TraceRecord>, json: &mut ObjectBuilder) {
+        let traces_json = records.iter().map(|r|
+            TraceRecordSerializer.serialize(r, false)).collect::<Vec<_>>();
+
+        json.set("traces", traces_json);
+    }
+}
@@ -2,43 +2,50 @@ use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::sync::{Arc, RwLock};
 
-use collect::http::json::{ResourceSerializer,
+use collect::http::json::{GroupSummarySerializer,
+    ResourceSerializer,
     ResourcesSerializer,
-    ResponsesSerializer};
-use collect::resource::{Resource, ResourceStore};
+    ResponsesSerializer,
+    RewritesSerializer,
+    TracesSerializer};
+use collect::resource::{check_unique_ids, group, rewrite, Labels, Resource,
+    Resources, ResourcesPatch, ResourceStore, Responses};
 use collect::resource::Response as CollectResponse;
+use collect::resource::selector::LabelSelector;
+use collect::resource::trace::TraceLog;
+use common::http::{self, query_param, split_query, Query};
 
 use hyper;
 use hyper::header::ContentType;
-use hyper::Server;
+use hyper::method::Method;
 use hyper::server::{Request, Response};
 use hyper::status::StatusCode;
 use hyper::net::Fresh;
 use hyper::uri::RequestUri::AbsolutePath;
 use jsonway;
 use jsonway::ObjectSerializer;
-use rustc_serialize::json;
+use rustc_serialize::{json, Decodable};
 
 pub fn start_server(resource_store: Arc<RwLock<ResourceStore>>,
-                    address: String,
+                    trace_log: Arc<RwLock<TraceLog>>,
+                    addresses: Vec<String>,
                     port: u16) {
-    let bind_addr: &str = &format!("{}:{}", address, port);
-    let rest_handler = RestHandler::new(resource_store);
-    let serve = move |req: Request, res: Response<Fresh>| {
+    let rest_handler = RestHandler::new(resource_store, trace_log);
+    http::serve(&addresses, port, move |req, res| {
         rest_handler.handle(req, res);
-    };
-    info!("Starting HTTP server on [{}]", bind_addr);
-    Server::http(bind_addr).map(|s| s.handle(serve).unwrap()).unwrap();
+    });
 }
 
 struct RestHandler {
     resource_store: Arc<RwLock<ResourceStore>>,
+    trace_log: Arc<RwLock<TraceLog>>,
     static_assets: HashMap<String, &'static str>,
 }
 
 impl RestHandler {
 
-    fn new(resource_store: Arc<RwLock<ResourceStore>>) -> RestHandler {
+    fn new(resource_store: Arc<RwLock<ResourceStore>>,
+           trace_log: Arc<RwLock<TraceLog>>) -> RestHandler {
 
         let mut static_assets = HashMap::new();
 
@@ -53,6 +60,7 @@ impl RestHandler {
 
         return RestHandler {
             resource_store: resource_store,
+            trace_log: trace_log,
             static_assets: static_assets,
         }
     }
@@ -67,23 +75,37 @@ impl RestHandler {
         let uri = req.uri.clone(); // prevent simultaneous mutable borrow
 
         match uri {
-            AbsolutePath(ref path) =>
-                match (&req.method, &path[..]) {
+            AbsolutePath(ref path) => {
+                let (path, query) = split_query(path);
+                match (&req.method, path) {
                     (&hyper::Get, "/resources") => {
-                        self.get_resources(res);
+                        self.get_resources(&query, res);
                     }
                     (&hyper::Post, "/resources") => {
                         self.post_resources(&mut req, res);
                     }
+                    (&Method::Put, "/resources") => {
+                        self.put_resources(&mut req, res);
+                    }
+                    (&Method::Patch, "/resources") => {
+                        self.patch_resources(&mut req, res);
+                    }
                     (&hyper::Get, "/responses") => {
-                        self.get_responses(res);
+                        self.get_responses(&query, res);
                     }
                     (&hyper::Get, "/responses/example") => {
                         self.get_responses_example(res);
                     }
-                    (&hyper::Get, abs_path)
-                          if abs_path == "/" ||
-                             abs_path.starts_with("/?") => {
+                    (&hyper::Get, "/groups") => {
+                        self.get_groups(&query, res);
+                    }
+                    (&hyper::Get, "/rewrites") => {
+                        self.get_rewrites(&query, res);
+                    }
+                    (&hyper::Get, "/traces") => {
+                        self.get_traces(res);
+                    }
+                    (&hyper::Get, "/") => {
                         self.get_index(res);
                     }
                     (&hyper::Get, abs_path)
@@ -97,8 +119,9 @@ impl RestHandler {
                         *res.status_mut() = hyper::NotFound;
                         return;
                     }
-                },
-                _ => { return; }
+                }
+            },
+            _ => { return; }
         };
     }
 
@@ -127,36 +150,24 @@ impl RestHandler {
         }
     }
 
-    fn get_resources(&self, mut res: Response<Fresh>) {
-        // Get the current set of resource targets.
-        let resources = self.resource_store.read().unwrap().resources();
-
-        let resources_json = ResourcesSerializer
-            .serialize(&resources, true)
-            .to_string();
+    fn get_resources(&self, query: &Query, res: Response<Fresh>) {
+        let selector = match parse_selector(query) {
+            Ok(selector) => selector,
+            Err(message) => return bad_request(res, message),
+        };
 
-        res.headers_mut().set(ContentType::json());
+        // Get the current set of resource targets.
+        let resources: Resources = self.resource_store.read().unwrap()
+            .select_resources(&selector);
 
-        let mut res = res.start().unwrap();
-        res.write_all(resources_json.as_bytes()).unwrap();
-        res.end().unwrap();
+        self.write_resources(&resources, res);
     }
 
     fn post_resources(&self, req: &mut Request, mut res: Response<Fresh>) {
-        let mut resource_raw = &mut String::new();
-        req.read_to_string(resource_raw).unwrap();
-        let decode_result = json::decode(resource_raw);
-        if let Err(decode_error) = decode_result {
-            warn!("{}", decode_error);
-            *res.status_mut() = StatusCode::BadRequest;
-            res.headers_mut().set(ContentType::plaintext());
-            let mut res = res.start().unwrap();
-            res.write_all(format!("{}", decode_error).as_bytes()).unwrap();
-            res.end().unwrap();
-            return;
-        }
-
-        let resource = decode_result.unwrap();
+        let resource: Resource = match decode_body(req) {
+            Ok(resource) => resource,
+            Err(message) => return bad_request(res, message),
+        };
         info!("Adding resource [{:?}]", resource);
 
         let resource_json = ResourceSerializer
@@ -171,9 +182,65 @@ impl RestHandler {
         res.end().unwrap();
     }
 
-    fn get_responses(&self, mut res: Response<Fresh>) {
+    fn put_resources(&self, req: &mut Request, res: Response<Fresh>) {
+        let resources: Resources = match decode_body(req) {
+            Ok(resources) => resources,
+            Err(message) => return bad_request(res, message),
+        };
+        if let Err(message) = check_unique_ids(&resources) {
+            return bad_request(res, message);
+        }
+        info!("Replacing resources with [{:?}]", resources);
+
+        let mut resource_store = self.resource_store.write().unwrap();
+        resource_store.replace_resources(resources);
+        let resources = resource_store.resources();
+        drop(resource_store);
+
+        self.write_resources(&resources, res);
+    }
+
+    fn patch_resources(&self, req: &mut Request, res: Response<Fresh>) {
+        let patch: ResourcesPatch = match decode_body(req) {
+            Ok(patch) => patch,
+            Err(message) => return bad_request(res, message),
+        };
+        if let Some(ref add) = patch.add {
+            if let Err(message) = check_unique_ids(add) {
+                return bad_request(res, message);
+            }
+        }
+        info!("Patching resources with [{:?}]", patch);
+
+        let mut resource_store = self.resource_store.write().unwrap();
+        resource_store.apply_patch(patch);
+        let resources = resource_store.resources();
+        drop(resource_store);
+
+        self.write_resources(&resources, res);
+    }
+
+    fn write_resources(&self, resources: &Resources, mut res: Response<Fresh>) {
+        let resources_json = ResourcesSerializer
+            .serialize(resources, true)
+            .to_string();
+
+        res.headers_mut().set(ContentType::json());
+
+        let mut res = res.start().unwrap();
+        res.write_all(resources_json.as_bytes()).unwrap();
+        res.end().unwrap();
+    }
+
+    fn get_responses(&self, query: &Query, mut res: Response<Fresh>) {
+        let selector = match parse_selector(query) {
+            Ok(selector) => selector,
+            Err(message) => return bad_request(res, message),
+        };
+
         // Get the current set of cached responses.
-        let responses = self.resource_store.read().unwrap().responses();
+        let responses: Responses = self.resource_store.read().unwrap()
+            .select_responses(&selector);
 
         let responses_json = ResponsesSerializer
             .serialize(&responses, true)
@@ -186,6 +253,70 @@ impl RestHandler {
         res.end().unwrap();
     }
 
+    fn get_groups(&self, query: &Query, mut res: Response<Fresh>) {
+        let label = match query_param(query, "label") {
+            Some(label) if label != "" => label.to_string(),
+            _ => return bad_request(
+                res,
+                "Missing required query parameter [label]".to_string()),
+        };
+        let selector = match parse_selector(query) {
+            Ok(selector) => selector,
+            Err(message) => return bad_request(res, message),
+        };
+
+        // Aggregate the cached responses of the selected resources.
+        let responses: Responses = self.resource_store.read().unwrap()
+            .select_responses(&selector);
+        let summary = group::summarize(&responses, &label);
+
+        let summary_json = GroupSummarySerializer
+            .serialize(&summary, true)
+            .to_string();
+
+        res.headers_mut().set(ContentType::json());
+
+        let mut res = res.start().unwrap();
+        res.write_all(summary_json.as_bytes()).unwrap();
+        res.end().unwrap();
+    }
+
+    fn get_rewrites(&self, query: &Query, mut res: Response<Fresh>) {
+        let selector = match parse_selector(query) {
+            Ok(selector) => selector,
+            Err(message) => return bad_request(res, message),
+        };
+
+        // Compare the observed source addresses of the selected resources.
+        let responses: Responses = self.resource_store.read().unwrap()
+            .select_responses(&selector);
+        let rewrites = rewrite::find_rewrites(&responses);
+
+        let rewrites_json = RewritesSerializer
+            .serialize(&rewrites, true)
+            .to_string();
+
+        res.headers_mut().set(ContentType::json());
+
+        let mut res = res.start().unwrap();
+        res.write_all(rewrites_json.as_bytes()).unwrap();
+        res.end().unwrap();
+    }
+
+    fn get_traces(&self, mut res: Response<Fresh>) {
+        let records = self.trace_log.read().unwrap().records();
+
+        let traces_json = TracesSerializer
+            .serialize(&records, true)
+            .to_string();
+
+        res.headers_mut().set(ContentType::json());
+
+        let mut res = res.start().unwrap();
+        res.write_all(traces_json.as_bytes()).unwrap();
+        res.end().unwrap();
+    }
+
     fn get_responses_example(&self, mut res: Response<Fresh>) {
         let mut responses = HashMap::new();
 
@@ -207,6 +338,8 @@ impl RestHandler {
                     json.push(
                         jsonway::object(|json| {
                             json.set("reachable", true);
+                            json.set("reachability", "degraded".to_string());
+                            json.set("lossPct", 40.0);
                             json.set("url", "http://d/status".to_string());
                         })
                     );
@@ -293,6 +426,7 @@ impl RestHandler {
             Resource {
                 id: "A".to_string(),
                 url: "http://a/status".to_string(),
+                labels: Labels::new(),
             },
             Some(CollectResponse {
                 url: "http://a/status".to_string(),
@@ -305,6 +439,7 @@ impl RestHandler {
             Resource {
                 id: "B".to_string(),
                 url: "http://b/status".to_string(),
+                labels: Labels::new(),
             },
             Some(CollectResponse {
                 url: "http://b/status".to_string(),
@@ -317,6 +452,7 @@ impl RestHandler {
             Resource {
                 id: "C".to_string(),
                 url: "http://c/status".to_string(),
+                labels: Labels::new(),
             },
             Some(CollectResponse {
                 url: "http://c/status".to_string(),
@@ -329,6 +465,7 @@ impl RestHandler {
             Resource {
                 id: "D".to_string(),
                 url: "http://d/status".to_string(),
+                labels: Labels::new(),
             },
             Some(CollectResponse {
                 url: "http://d/status".to_string(),
@@ -341,6 +478,7 @@ impl RestHandler {
             Resource {
                 id: "E".to_string(),
                 url: "http://e/status".to_string(),
+                labels: Labels::new(),
             },
             None
         );
@@ -359,6 +497,30 @@ impl RestHandler {
     }
 }
 
+fn decode_body<T: Decodable>(req: &mut Request) -> Result<T, String> {
+    let mut raw = String::new();
+    if let Err(read_error) = req.read_to_string(&mut raw) {
+        return Err(format!("{}", read_error));
+    }
+    json::decode(&raw).map_err(|decode_error| format!("{}", decode_error))
+}
+
+fn parse_selector(query: &Query) -> Result<LabelSelector, String> {
+    match query_param(query, "selector") {
+        Some(raw) => LabelSelector::parse(raw),
+        None => Ok(LabelSelector::everything()),
+    }
+}
+
+fn bad_request(mut res: Response<Fresh>, message: String) {
+    warn!("{}", message);
+    *res.status_mut() = StatusCode::BadRequest;
+    res.headers_mut().set(ContentType::plaintext());
+    let mut res = res.start().unwrap();
+    res.write_all(message.as_bytes()).unwrap();
+    res.end().unwrap();
+}
+
 fn guess_content_type(name: &String) -> String {
     match name {
         ref r if r.ends_with(".css") => "text/css".to_string(),
@@ -1,11 +1,16 @@
+use std::collections::HashMap;
 use std::io::Read;
 use std::sync::{Arc, RwLock};
 use std::thread;
 
-use collect::resource::{Resource, ResourceStore, Response};
+use collect::resource::{Resource, Resources, ResourcesPatch, ResourceStore,
+    Response};
+use collect::resource::trace::{TraceLog, Tracer};
+use common::dns::{self, Resolver};
+use common::http;
+use common::net::{Resolution, Source};
 
 use hyper::client::Response as HttpResponse;
-use hyper::Client;
 use hyper::error::Error;
 use hyper::header::Connection;
 use hyper::http::RawStatus;
@@ -13,8 +18,22 @@ use mio::{EventLoop, Handler};
 use rustc_serialize::json::Json;
 use threadpool::ThreadPool;
 
+/// Starts fetching the stored resources.  Resources declared with a DNS
+/// discovery URL (see `common::dns`) are not fetched themselves; instead
+/// they are re-resolved every interval, and each discovered endpoint is
+/// stored as a separate resource with the id `<id>@<host>:<port>`.
+///
+/// If a trace log is given, whenever a target of a fetched probe status
+/// becomes degraded or unreachable the probe is asked to trace the path to
+/// it, and the result is added to the log.
+///
+/// The hosts of resources, and of the probes asked for traces, are
+/// resolved as given by the resolution.
 pub fn start_client_driver(http_request_ms: u64,
-                           resource_store: Arc<RwLock<ResourceStore>>) {
+                           resource_store: Arc<RwLock<ResourceStore>>,
+                           resolver: Option<Resolver>,
+                           resolution: Resolution,
+                           trace_log: Option<Arc<RwLock<TraceLog>>>) {
     info!("Starting client driver");
     let mut event_loop = EventLoop::new().unwrap();
     let _ = event_loop.timeout_ms((), http_request_ms);
@@ -22,6 +41,11 @@ pub fn start_client_driver(http_request_ms: u64,
         let _ = event_loop.run(&mut ClientHandler {
             http_request_ms: http_request_ms,
             resource_store: resource_store,
+            resolver: resolver,
+            tracer: trace_log.map(|trace_log|
+                Arc::new(Tracer::new(trace_log, resolution.clone()))),
+            resolution: resolution,
+            discovered: HashMap::new(),
             thread_pool: ThreadPool::new(4),
         });
     });
@@ -30,9 +54,79 @@ pub fn start_client_driver(http_request_ms: u64,
 struct ClientHandler {
     http_request_ms: u64,
     resource_store: Arc<RwLock<ResourceStore>>,
+    resolver: Option<Resolver>,
+    resolution: Resolution,
+    tracer: Option<Arc<Tracer>>,
+    discovered: HashMap<String, Resources>,
     thread_pool: ThreadPool,
 }
 
+impl ClientHandler {
+    /// Expands the stored discovery resources, syncs the discovered
+    /// resources into the store and returns the resources to fetch.  If a
+    /// discovery URL cannot be resolved, its previously discovered resources
+    /// are kept.
+    fn discover(&mut self) -> Resources {
+        let declared: Resources = self.resource_store.read().unwrap()
+            .resources()
+            .into_iter()
+            .filter(|r| dns::is_discovery_url(&r.url))
+            .collect();
+
+        let mut removed: Vec<String> = vec!();
+        let mut added: Resources = vec!();
+
+        // Forget the discovered resources of removed declarations.
+        let stale_ids: Vec<String> = self.discovered.keys()
+            .filter(|id| !declared.iter().any(|r| &r.id == *id))
+            .cloned()
+            .collect();
+        for id in stale_ids {
+            if let Some(resources) = self.discovered.remove(&id) {
+                removed.extend(resources.into_iter().map(|r| r.id));
+            }
+        }
+
+        for resource in declared {
+            let expanded = match self.resolver {
+                Some(ref resolver) => dns::expand_url(&resource.url, resolver),
+                None => Err("No DNS resolver is configured".to_string()),
+            };
+            let discovered: Resources = match expanded {
+                Ok(discovered) => discovered.into_iter().map(|d| Resource {
+                    id: format!("{}@{}", resource.id, d.endpoint),
+                    url: d.url,
+                    labels: resource.labels.clone(),
+                }).collect(),
+                Err(cause) => {
+                    warn!("Failed to resolve resource [{}]: {}",
+                          resource.id,
+                          cause);
+                    continue;
+                },
+            };
+
+            if let Some(previous) = self.discovered.get(&resource.id) {
+                removed.extend(previous.iter()
+                    .filter(|p| !discovered.iter().any(|d| d.id == p.id))
+                    .map(|p| p.id.clone()));
+            }
+            added.extend(discovered.iter().cloned());
+            self.discovered.insert(resource.id, discovered);
+        }
+
+        let mut resource_store = self.resource_store.write().unwrap();
+        resource_store.apply_patch(ResourcesPatch {
+            add: Some(added),
+            remove: Some(removed),
+        });
+        resource_store.resources()
+            .into_iter()
+            .filter(|r| !dns::is_discovery_url(&r.url))
+            .collect()
+    }
+}
+
 impl Handler for ClientHandler {
     type Timeout = ();
     type Message = Resource;
@@ -42,7 +136,7 @@ impl Handler for ClientHandler {
                _: ()) {
         info!("Fetching all resources");
         let loop_channel = event_loop.channel();
-        for resource in self.resource_store.read().unwrap().resources() {
+        for resource in self.discover() {
             let _ = loop_channel.send(resource);
         }
         let _ = event_loop.timeout_ms((), self.http_request_ms);
@@ -52,46 +146,66 @@ impl Handler for ClientHandler {
               _: &mut EventLoop<ClientHandler>,
               resource: Resource) {
         let resource_store = self.resource_store.clone();
+        let resolution = self.resolution.clone();
+        let tracer = self.tracer.clone();
         self.thread_pool.execute(move || {
             info!("Fetching resource: [{}]", &resource.url);
 
-            let client = Client::new();
+            let previous: Option<Response> = match tracer {
+                Some(_) => resource_store.read().unwrap().response(&resource),
+                None => None,
+            };
+
+            let client =
+                http::client_from(None, Source::default(), resolution.clone());
 
             let response_result: Result<HttpResponse, Error> =
                 client.get(&resource.url)
                     .header(Connection::close())
                     .send();
 
-            // Obtain an exclusive write lock to the status cache.
-            let mut resource_store = resource_store.write().unwrap();
-
-            match response_result {
+            let response = match response_result {
                 Ok(mut http_response) => {
                     let body = &mut String::new();
-                    http_response.read_to_string(body).unwrap();
-                    let body_json = Json::from_str(body);
-
-                    if let Err(parse_error) = body_json {
-                        let error_str = format!("{}", parse_error);
-                        warn!("Failed to parse response body as JSON: [{}]",
-                            error_str);
-                        resource_store.save_response(resource, None);
-                        return;
+                    match http_response.read_to_string(body) {
+                        Ok(_) => match Json::from_str(body) {
+                            Ok(body_json) => {
+                                let &RawStatus(status_code, _) =
+                                    http_response.status_raw();
+
+                                Some(Response {
+                                    url: resource.url.clone(),
+                                    status_code: status_code,
+                                    json: body_json,
+                                })
+                            },
+                            Err(parse_error) => {
+                                warn!("Failed to parse response body as \
+                                       JSON: [{}]",
+                                      parse_error);
+                                None
+                            },
+                        },
+                        Err(cause) => {
+                            warn!("Failed to read [{}]: {}",
+                                  resource.url, cause);
+                            None
+                        },
                     }
+                },
+                Err(_) => None,
+            };
 
-                    let &RawStatus(status_code, _) =
-                        http_response.status_raw();
+            resource_store.write().unwrap()
+                .save_response(resource.clone(), response.clone());
 
-                    let response = Response {
-                        url: resource.url.clone(),
-                        status_code: status_code,
-                        json: body_json.unwrap(),
-                    };
-                    resource_store.save_response(resource, Some(response));
-                },
-                Err(_) => resource_store.save_response(resource, None),
+            if let (Some(tracer), Some(previous), Some(current)) =
+                    (tracer, previous, response) {
+                tracer.trace_transitions(&resource.id,
+                                         &resource.url,
+                                         &previous.json,
+                                         &current.json);
             }
         });
     }
 }
-
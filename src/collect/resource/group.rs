@@ -0,0 +1,85 @@
+use std::collections::{BTreeMap, HashMap};
+
+use collect::resource::Responses;
+
+/// Aggregated reachability from the members of one resource group to the
+/// members of another, where groups are formed by the value of a label.
+#[derive(Clone, Debug)]
+pub struct GroupEdge {
+    pub from: String,
+    pub to: String,
+    pub reachable: u32,
+    /// Targets that answered only some of the attempts of a round.
+    pub degraded: u32,
+    pub unreachable: u32,
+}
+
+pub struct GroupSummary {
+    pub label: String,
+    pub edges: Vec<GroupEdge>,
+}
+
+/// Summarizes the cached probe responses at the group level.
+///
+/// Each probe target reported in a cached response is matched against the
+/// URL of a known resource.  Resources without the grouping label, and
+/// targets that do not correspond to a known resource, are ignored.
+pub fn summarize(responses: &Responses, label: &str) -> GroupSummary {
+    let groups_by_url: HashMap<String, String> = responses.keys()
+        .filter_map(|resource| resource.labels.get(label).map(|group|
+            (resource.url.clone(), group.clone())))
+        .collect();
+
+    let mut counts: BTreeMap<(String, String), (u32, u32, u32)> =
+        BTreeMap::new();
+
+    for (resource, response) in responses {
+        let from = match resource.labels.get(label) {
+            Some(group) => group,
+            None => continue,
+        };
+        let targets = match response.as_ref()
+            .and_then(|r| r.json.find_path(&["status", "targets"]))
+            .and_then(|t| t.as_array()) {
+            Some(targets) => targets,
+            None => continue,
+        };
+
+        for target in targets {
+            let url = target.find("url").and_then(|u| u.as_string());
+            let to = match url.and_then(|u| groups_by_url.get(u)) {
+                Some(group) => group,
+                None => continue,
+            };
+            let reachable = target.find("reachable")
+                .and_then(|r| r.as_boolean())
+                .unwrap_or(false);
+            let degraded = target.find("reachability")
+                .and_then(|r| r.as_string())
+                .map(|r| r == "degraded")
+                .unwrap_or(false);
+
+            let count = counts.entry((from.clone(), to.clone()))
+                .or_insert((0, 0, 0));
+            if degraded {
+                count.1 += 1;
+            } else if reachable {
+                count.0 += 1;
+            } else {
+                count.2 += 1;
+            }
+        }
+    }
+
+    let edges = counts.into_iter()
+        .map(|((from, to), (reachable, degraded, unreachable))| GroupEdge {
+            from: from,
+            to: to,
+            reachable: reachable,
+            degraded: degraded,
+            unreachable: unreachable,
+        })
+        .collect();
+
+    GroupSummary { label: label.to_string(), edges: edges }
+}
@@ -0,0 +1,72 @@
+use collect::resource::{Resource, Resources, ResourceStore, Response,
+    Responses};
+
+/// Keeps the resources and their responses in memory.
+pub struct MemoryResourceStore {
+    responses: Responses,
+}
+
+impl MemoryResourceStore {
+    pub fn new(resources: Vec<Resource>) -> MemoryResourceStore {
+        let mut result = MemoryResourceStore {
+            responses: Responses::new(),
+        };
+        for resource in resources.iter() {
+            result.responses.insert(resource.clone(), None);
+        }
+        result
+    }
+}
+
+impl ResourceStore for MemoryResourceStore {
+    fn resources(&self) -> Vec<Resource> {
+        self.responses.keys().map(|r| r.clone()).collect()
+    }
+
+    fn save_resource(&mut self, resource: Resource) {
+        if self.responses.contains_key(&resource) {
+            return;
+        }
+        self.remove_resource(&resource.id);
+        self.responses.insert(resource, None);
+    }
+
+    fn remove_resource(&mut self, id: &str) -> bool {
+        let existing = self.responses.keys()
+            .find(|r| r.id == id)
+            .map(|r| r.clone());
+        match existing {
+            Some(resource) => {
+                self.responses.remove(&resource);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn replace_resources(&mut self, resources: Resources) {
+        let mut responses = Responses::new();
+        for resource in resources {
+            let response = self.responses.remove(&resource)
+                .unwrap_or(None);
+            responses.insert(resource, response);
+        }
+        self.responses = responses;
+    }
+
+    fn responses(&self) -> Responses {
+        self.responses.clone()
+    }
+
+    fn response(&self, resource: &Resource) -> Option<Response> {
+        self.responses.get(resource).and_then(|r| r.clone())
+    }
+
+    fn save_response(&mut self,
+                     resource: Resource,
+                     response: Option<Response>) {
+        if let Some(cached) = self.responses.get_mut(&resource) {
+            *cached = response;
+        }
+    }
+}
@@ -1,13 +1,46 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
 
-use rustc_serialize::json;
+use collect::resource::selector::LabelSelector;
+
+use rustc_serialize::{json, Decodable, Decoder};
 
 pub mod client;
+pub mod group;
+pub mod memory;
+pub mod rewrite;
+pub mod selector;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod trace;
+pub mod watcher;
+
+pub type Labels = BTreeMap<String, String>;
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq, RustcDecodable)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Resource {
     pub id: String,
     pub url: String,
+    pub labels: Labels,
+}
+
+// Implemented by hand so that `labels` may be omitted from the JSON
+// representation of a resource.
+impl Decodable for Resource {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Resource, D::Error> {
+        d.read_struct("Resource", 3, |d| {
+            let id = try!(d.read_struct_field("id", 0, Decodable::decode));
+            let url = try!(d.read_struct_field("url", 1, Decodable::decode));
+            let labels: Option<Labels> =
+                try!(d.read_struct_field("labels", 2, Decodable::decode));
+            Ok(Resource {
+                id: id,
+                url: url,
+                labels: labels.unwrap_or(Labels::new()),
+            })
+        })
+    }
 }
 
 pub type Resources = Vec<Resource>;
@@ -21,36 +54,125 @@ pub struct Response {
 
 pub type Responses = HashMap<Resource, Option<Response>>;
 
-pub struct ResourceStore {
-    responses: Responses,
+/// A batch of changes to apply to the resource set in one step.
+#[derive(Debug, RustcDecodable)]
+pub struct ResourcesPatch {
+    pub add: Option<Resources>,
+    pub remove: Option<Vec<String>>,
 }
 
-impl ResourceStore {
-    pub fn new(resources: Vec<Resource>) -> ResourceStore {
-        let mut result = ResourceStore {
-            responses: Responses::new(),
-        };
-        for resource in resources.iter() {
-            result.responses.insert(resource.clone(), None);
+/// Storage for the resource set and the most recent response fetched for
+/// each resource.
+pub trait ResourceStore: Send + Sync {
+    fn resources(&self) -> Resources;
+
+    /// The resources whose labels match the selector.
+    fn select_resources(&self, selector: &LabelSelector) -> Resources {
+        self.resources()
+            .into_iter()
+            .filter(|r| selector.matches(&r.labels))
+            .collect()
+    }
+
+    /// Adds the resource, replacing any existing resource with the same id.
+    /// The cached response is kept if the resource is unchanged.
+    fn save_resource(&mut self, resource: Resource);
+
+    /// Removes the resource with the supplied id, returning whether one was
+    /// present.
+    fn remove_resource(&mut self, id: &str) -> bool;
+
+    /// Replaces the entire resource set.  Cached responses are kept for
+    /// resources that are unchanged.
+    fn replace_resources(&mut self, resources: Resources);
+
+    /// Applies a batch of removals followed by a batch of additions.
+    fn apply_patch(&mut self, patch: ResourcesPatch) {
+        for id in patch.remove.unwrap_or(vec!()) {
+            self.remove_resource(&id);
+        }
+        for resource in patch.add.unwrap_or(vec!()) {
+            self.save_resource(resource);
         }
-        result
     }
 
-    pub fn resources(&self) -> Vec<Resource> {
-        self.responses.keys().map(|r| r.clone()).collect()
+    fn responses(&self) -> Responses;
+
+    /// The cached responses of the resources whose labels match the
+    /// selector.
+    fn select_responses(&self, selector: &LabelSelector) -> Responses {
+        self.responses()
+            .into_iter()
+            .filter(|&(ref r, _)| selector.matches(&r.labels))
+            .collect()
     }
 
-    pub fn save_resource(&mut self, resource: Resource) {
-        self.save_response(resource, None);
+    /// The cached response for the resource, if it is stored unchanged.
+    fn response(&self, resource: &Resource) -> Option<Response> {
+        self.responses().remove(resource).and_then(|r| r)
+    }
+
+    /// Caches the response for the resource.  Responses for resources that
+    /// have since been removed or changed are discarded.
+    fn save_response(&mut self,
+                     resource: Resource,
+                     response: Option<Response>);
+}
+
+/// Returns an error naming the first id that occurs more than once.
+pub fn check_unique_ids(resources: &Resources) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for resource in resources {
+        if !ids.insert(&resource.id) {
+            return Err(format!("Duplicate resource id [{}]", resource.id));
+        }
+    }
+    Ok(())
+}
+
+/// Reads a JSON array of resources from the file at the supplied path.
+pub fn read_resources_file(path: &str) -> Result<Resources, String> {
+    let mut raw = String::new();
+    let read_result = File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut raw));
+    if let Err(cause) = read_result {
+        return Err(format!("Failed to read file [{}]!\n{}", path, cause));
+    }
+
+    let resources: Resources = match json::decode(&raw) {
+        Ok(resources) => resources,
+        Err(cause) => return Err(
+            format!("Failed to parse file [{}] as resources!\n{}",
+                    path,
+                    cause)),
+    };
+    try!(check_unique_ids(&resources));
+    Ok(resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_unique_ids, Labels, Resource};
+
+    fn resource(id: &str) -> Resource {
+        Resource {
+            id: id.to_string(),
+            url: format!("http://{}:9000/status", id),
+            labels: Labels::new(),
+        }
     }
 
-    pub fn responses(&self) -> Responses {
-        self.responses.clone()
+    #[test]
+    fn unique_ids() {
+        assert_eq!(check_unique_ids(&vec!()), Ok(()));
+        assert_eq!(check_unique_ids(&vec!(resource("a"), resource("b"))),
+                   Ok(()));
     }
 
-    pub fn save_response(&mut self,
-                         resource: Resource,
-                         response: Option<Response>) {
-        self.responses.insert(resource, response);
+    #[test]
+    fn duplicate_ids() {
+        let resources = vec!(resource("a"), resource("b"), resource("a"));
+        assert_eq!(check_unique_ids(&resources),
+                   Err("Duplicate resource id [a]".to_string()));
     }
 }
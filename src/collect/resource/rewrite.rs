@@ -0,0 +1,118 @@
+use std::net::IpAddr;
+
+use collect::resource::Responses;
+
+use rustc_serialize::json::Json;
+
+/// A probe whose source address was observed by one of its targets as an
+/// address other than the one the probe left from, e.g. because of SNAT or
+/// masquerading along the way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rewrite {
+    /// Id of the resource (probe) whose traffic was rewritten.
+    pub id: String,
+    /// The local address the probe reported leaving from.
+    pub source_address: String,
+    pub target_url: String,
+    pub observed_address: String,
+}
+
+/// Finds the probe targets that report an observed source address other
+/// than the local address the probe reported leaving from.  Targets that do
+/// not report both are ignored.
+pub fn find_rewrites(responses: &Responses) -> Vec<Rewrite> {
+    let mut rewrites = vec!();
+
+    for (resource, response) in responses {
+        let targets = match response.as_ref()
+            .and_then(|r| r.json.find_path(&["status", "targets"]))
+            .and_then(|t| t.as_array()) {
+            Some(targets) => targets,
+            None => continue,
+        };
+
+        for target in targets {
+            let (source, observed) =
+                match (ip_at(target, &["source", "address"]),
+                       ip_at(target, &["observedSourceAddress"])) {
+                    (Some(source), Some(observed)) => (source, observed),
+                    _ => continue,
+                };
+            if source == observed {
+                continue;
+            }
+            rewrites.push(Rewrite {
+                id: resource.id.clone(),
+                source_address: format!("{}", source),
+                target_url: target.find("url")
+                    .and_then(|u| u.as_string())
+                    .unwrap_or("")
+                    .to_string(),
+                observed_address: format!("{}", observed),
+            });
+        }
+    }
+
+    rewrites.sort_by(|a, b|
+        (&a.id, &a.target_url).cmp(&(&b.id, &b.target_url)));
+    rewrites
+}
+
+/// The IP address at the path of a JSON object, if any.
+fn ip_at(json: &Json, path: &[&str]) -> Option<IpAddr> {
+    json.find_path(path)
+        .and_then(|a| a.as_string())
+        .and_then(|a| a.parse::<IpAddr>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use collect::resource::{Labels, Resource, Response, Responses};
+
+    use rustc_serialize::json::Json;
+
+    use super::{find_rewrites, Rewrite};
+
+    fn responses(id: &str, status: &str) -> Responses {
+        let resource = Resource {
+            id: id.to_string(),
+            url: "http://probe.example.com:9000/status".to_string(),
+            labels: Labels::new(),
+        };
+        let response = Response {
+            url: resource.url.clone(),
+            status_code: 200,
+            json: Json::from_str(status).unwrap(),
+        };
+        let mut responses = Responses::new();
+        responses.insert(resource, Some(response));
+        responses
+    }
+
+    #[test]
+    fn observed_addresses_other_than_the_source_are_rewrites() {
+        let responses = responses("probe-a", r#"{"status": {"targets": [
+            {"url": "http://10.0.4.8:9000/status",
+             "source": {"address": "10.0.4.7"},
+             "observedSourceAddress": "172.17.0.1"},
+            {"url": "http://10.0.4.9:9000/status",
+             "source": {"address": "10.0.4.7"},
+             "observedSourceAddress": "10.0.4.7"},
+            {"url": "http://10.0.4.10:9000/status",
+             "observedSourceAddress": "172.17.0.1"},
+            {"url": "http://10.0.4.11:9000/status",
+             "source": {"address": "10.0.4.7"}}
+        ]}}"#);
+        assert_eq!(find_rewrites(&responses), vec!(Rewrite {
+            id: "probe-a".to_string(),
+            source_address: "10.0.4.7".to_string(),
+            target_url: "http://10.0.4.8:9000/status".to_string(),
+            observed_address: "172.17.0.1".to_string(),
+        }));
+    }
+
+    #[test]
+    fn responses_without_targets_are_ignored() {
+        assert!(find_rewrites(&responses("probe-a", "{}")).is_empty());
+    }
+}
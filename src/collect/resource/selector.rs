@@ -0,0 +1,131 @@
+use collect::resource::Labels;
+
+/// A single requirement of a label selector.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Requirement {
+    /// The label is present, with any value.
+    Exists(String),
+    /// The label is present with exactly this value.
+    Equals(String, String),
+    /// The label is absent, or present with a different value.
+    NotEquals(String, String),
+}
+
+impl Requirement {
+    pub fn matches(&self, labels: &Labels) -> bool {
+        match *self {
+            Requirement::Exists(ref key) => labels.contains_key(key),
+            Requirement::Equals(ref key, ref value) =>
+                labels.get(key) == Some(value),
+            Requirement::NotEquals(ref key, ref value) =>
+                labels.get(key) != Some(value),
+        }
+    }
+}
+
+/// A conjunction of label requirements, written as a comma-delimited list,
+/// e.g: `app=frontend,zone!=us-east-1a,canary`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelSelector {
+    requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    /// A selector that matches every set of labels.
+    pub fn everything() -> LabelSelector {
+        LabelSelector { requirements: vec!() }
+    }
+
+    pub fn parse(raw: &str) -> Result<LabelSelector, String> {
+        let mut requirements = vec!();
+        for term in raw.split(',').map(|t| t.trim()).filter(|t| *t != "") {
+            let requirement =
+                if let Some(index) = term.find("!=") {
+                    Requirement::NotEquals(
+                        term[..index].trim().to_string(),
+                        term[index + 2..].trim().to_string())
+                } else if let Some(index) = term.find("==") {
+                    Requirement::Equals(
+                        term[..index].trim().to_string(),
+                        term[index + 2..].trim().to_string())
+                } else if let Some(index) = term.find('=') {
+                    Requirement::Equals(
+                        term[..index].trim().to_string(),
+                        term[index + 1..].trim().to_string())
+                } else {
+                    Requirement::Exists(term.to_string())
+                };
+
+            let key_is_empty = match requirement {
+                Requirement::Exists(ref key) |
+                Requirement::Equals(ref key, _) |
+                Requirement::NotEquals(ref key, _) => key.is_empty(),
+            };
+            if key_is_empty {
+                return Err(format!("Invalid label selector term [{}]", term));
+            }
+            requirements.push(requirement);
+        }
+        Ok(LabelSelector { requirements: requirements })
+    }
+
+    pub fn requirements(&self) -> &[Requirement] {
+        &self.requirements
+    }
+
+    pub fn matches(&self, labels: &Labels) -> bool {
+        self.requirements.iter().all(|r| r.matches(labels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use collect::resource::Labels;
+
+    use super::{LabelSelector, Requirement};
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs.iter()
+            .map(|&(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_requirements() {
+        let selector =
+            LabelSelector::parse(" app=web, tier == front,zone!=a ,canary,")
+                .unwrap();
+        assert_eq!(selector.requirements, vec!(
+            Requirement::Equals("app".to_string(), "web".to_string()),
+            Requirement::Equals("tier".to_string(), "front".to_string()),
+            Requirement::NotEquals("zone".to_string(), "a".to_string()),
+            Requirement::Exists("canary".to_string())));
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(LabelSelector::parse(""), Ok(LabelSelector::everything()));
+        assert_eq!(LabelSelector::parse(" , "),
+                   Ok(LabelSelector::everything()));
+    }
+
+    #[test]
+    fn parse_empty_key() {
+        assert_eq!(LabelSelector::parse("app=web,=front"),
+                   Err("Invalid label selector term [=front]".to_string()));
+        assert!(LabelSelector::parse("!=a").is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let selector = LabelSelector::parse("app=web,zone!=a,canary").unwrap();
+        assert!(selector.matches(
+            &labels(&[("app", "web"), ("zone", "b"), ("canary", "")])));
+        assert!(selector.matches(&labels(&[("app", "web"), ("canary", "")])));
+        assert!(!selector.matches(
+            &labels(&[("app", "web"), ("zone", "a"), ("canary", "")])));
+        assert!(!selector.matches(&labels(&[("app", "web")])));
+        assert!(!selector.matches(&labels(&[("app", "db"), ("canary", "")])));
+        assert!(LabelSelector::everything().matches(&Labels::new()));
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use collect::resource::{Labels, Resource, Resources, ResourcesPatch,
+    ResourceStore, Response, Responses};
+use collect::resource::selector::{LabelSelector, Requirement};
+
+use rusqlite::Connection;
+use rusqlite::types::ToSql;
+use rustc_serialize::json::{self, Json};
+
+/// Keeps the resources and their responses in an embedded SQLite database,
+/// so that they persist across restarts.  Resources are looked up by id and
+/// label selectors are evaluated by the database, so changing or selecting
+/// part of the resource set does not load all of it.
+pub struct SqliteResourceStore {
+    // Connections are not `Sync`, so access is serialized.
+    connection: Mutex<Connection>,
+}
+
+impl SqliteResourceStore {
+    /// Opens (or creates) the database at the supplied path.
+    pub fn open(path: &str) -> Result<SqliteResourceStore, String> {
+        let connection = try!(Connection::open(path)
+            .map_err(|e| format!("Failed to open database [{}]: {}",
+                                 path, e)));
+        try!(connection.execute_batch("
+            CREATE TABLE IF NOT EXISTS resources (
+                id          TEXT PRIMARY KEY,
+                url         TEXT NOT NULL,
+                labels      TEXT NOT NULL,
+                status_code INTEGER,
+                body        TEXT
+            );
+            CREATE TABLE IF NOT EXISTS labels (
+                resource_id TEXT NOT NULL,
+                key         TEXT NOT NULL,
+                value       TEXT NOT NULL,
+                PRIMARY KEY (resource_id, key)
+            );").map_err(|e| format!("Failed to create tables: {}", e)));
+
+        Ok(SqliteResourceStore { connection: Mutex::new(connection), })
+    }
+
+    /// Queries the resources whose rows meet the condition.
+    fn query_resources(&self,
+                       condition: &str,
+                       params: &[&ToSql]) -> Result<Resources, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = try!(connection.prepare(&format!(
+            "SELECT id, url, labels FROM resources WHERE {}", condition))
+            .map_err(|e| format!("Failed to query resources: {}", e)));
+        let rows = try!(statement.query_map(params, |row| Resource {
+            id: row.get(0),
+            url: row.get(1),
+            labels: decode_labels(&row.get::<_, String>(2)),
+        }).map_err(|e| format!("Failed to query resources: {}", e)));
+
+        Ok(rows.filter_map(|row| match row {
+            Ok(row) => Some(row),
+            Err(cause) => {
+                warn!("Failed to read resource: {}", cause);
+                None
+            },
+        }).collect())
+    }
+
+    /// Queries the resources whose rows meet the condition, with their
+    /// cached responses.
+    fn query_responses(&self,
+                       condition: &str,
+                       params: &[&ToSql]) -> Result<Responses, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = try!(connection.prepare(&format!(
+            "SELECT id, url, labels, status_code, body FROM resources
+             WHERE {}", condition))
+            .map_err(|e| format!("Failed to query responses: {}", e)));
+        let rows = try!(statement.query_map(params, |row| {
+            let resource = Resource {
+                id: row.get(0),
+                url: row.get(1),
+                labels: decode_labels(&row.get::<_, String>(2)),
+            };
+            let status_code: Option<i64> = row.get(3);
+            let body: Option<String> = row.get(4);
+            let response = match (status_code, body) {
+                (Some(status_code), Some(body)) =>
+                    Json::from_str(&body).ok().map(|json| Response {
+                        url: resource.url.clone(),
+                        status_code: status_code as u16,
+                        json: json,
+                    }),
+                _ => None,
+            };
+            (resource, response)
+        }).map_err(|e| format!("Failed to query responses: {}", e)));
+
+        Ok(rows.filter_map(|row| match row {
+            Ok(row) => Some(row),
+            Err(cause) => {
+                warn!("Failed to read resource: {}", cause);
+                None
+            },
+        }).collect())
+    }
+
+    fn remove(&self, id: &str) -> Result<bool, String> {
+        remove(&self.connection.lock().unwrap(), id)
+    }
+
+    /// Replaces the resources in a single transaction, which is rolled back
+    /// if any statement fails.
+    fn replace(&self, resources: &Resources) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = try!(connection.transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e)));
+        for existing in try!(ids_except(&transaction, resources)) {
+            try!(remove(&transaction, &existing));
+        }
+        for resource in resources {
+            try!(save(&transaction, resource));
+        }
+        transaction.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))
+    }
+
+    /// Applies the patch in a single transaction, which is rolled back if
+    /// any statement fails.
+    fn patch(&self, patch: ResourcesPatch) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = try!(connection.transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e)));
+        for id in patch.remove.unwrap_or(vec!()) {
+            try!(remove(&transaction, &id));
+        }
+        for resource in patch.add.unwrap_or(vec!()) {
+            try!(save(&transaction, &resource));
+        }
+        transaction.commit()
+            .map_err(|e| format!("Failed to commit transaction: {}", e))
+    }
+
+    fn update_response(&self,
+                       resource: &Resource,
+                       response: Option<Response>) -> Result<(), String> {
+        let labels = encode_labels(&resource.labels);
+        let (status_code, body) = match response {
+            Some(response) => (Some(response.status_code as i64),
+                               Some(response.json.to_string())),
+            None => (None, None),
+        };
+
+        // Only update the row if the resource is unchanged.
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "UPDATE resources SET status_code = $1, body = $2
+             WHERE id = $3 AND url = $4 AND labels = $5",
+            &[&status_code, &body, &resource.id, &resource.url, &labels])
+            .map(|_| ())
+            .map_err(|e| format!("Failed to save response of [{}]: {}",
+                                 resource.id, e))
+    }
+}
+
+// Failures are logged and leave the stored state unchanged, as the
+// collector keeps running and retries on the next poll.
+impl ResourceStore for SqliteResourceStore {
+    fn resources(&self) -> Resources {
+        self.query_resources("1", &[]).unwrap_or_else(|cause| {
+            warn!("{}", cause);
+            vec!()
+        })
+    }
+
+    fn select_resources(&self, selector: &LabelSelector) -> Resources {
+        let (condition, values) = selector_condition(selector);
+        let params: Vec<&ToSql> = values.iter().map(|v| v as &ToSql).collect();
+        self.query_resources(&condition, &params).unwrap_or_else(|cause| {
+            warn!("{}", cause);
+            vec!()
+        })
+    }
+
+    fn save_resource(&mut self, resource: Resource) {
+        if let Err(cause) = save(&self.connection.lock().unwrap(), &resource) {
+            warn!("{}", cause);
+        }
+    }
+
+    fn remove_resource(&mut self, id: &str) -> bool {
+        self.remove(id).unwrap_or_else(|cause| {
+            warn!("{}", cause);
+            false
+        })
+    }
+
+    fn replace_resources(&mut self, resources: Resources) {
+        if let Err(cause) = self.replace(&resources) {
+            warn!("{}", cause);
+        }
+    }
+
+    fn apply_patch(&mut self, patch: ResourcesPatch) {
+        if let Err(cause) = self.patch(patch) {
+            warn!("{}", cause);
+        }
+    }
+
+    fn responses(&self) -> Responses {
+        self.query_responses("1", &[]).unwrap_or_else(|cause| {
+            warn!("{}", cause);
+            Responses::new()
+        })
+    }
+
+    fn select_responses(&self, selector: &LabelSelector) -> Responses {
+        let (condition, values) = selector_condition(selector);
+        let params: Vec<&ToSql> = values.iter().map(|v| v as &ToSql).collect();
+        self.query_responses(&condition, &params).unwrap_or_else(|cause| {
+            warn!("{}", cause);
+            Responses::new()
+        })
+    }
+
+    fn response(&self, resource: &Resource) -> Option<Response> {
+        let labels = encode_labels(&resource.labels);
+        self.query_responses("id = $1 AND url = $2 AND labels = $3",
+                             &[&resource.id, &resource.url, &labels])
+            .unwrap_or_else(|cause| {
+                warn!("{}", cause);
+                Responses::new()
+            })
+            .remove(resource)
+            .and_then(|r| r)
+    }
+
+    fn save_response(&mut self,
+                     resource: Resource,
+                     response: Option<Response>) {
+        if let Err(cause) = self.update_response(&resource, response) {
+            warn!("{}", cause);
+        }
+    }
+}
+
+/// Saves the resource, replacing any stored resource with the same id.  A
+/// resource that is stored unchanged is left alone to keep its response.
+fn save(connection: &Connection, resource: &Resource) -> Result<(), String> {
+    let labels = encode_labels(&resource.labels);
+    let unchanged: i64 = try!(connection.query_row(
+        "SELECT COUNT(*) FROM resources
+         WHERE id = $1 AND url = $2 AND labels = $3",
+        &[&resource.id, &resource.url, &labels],
+        |row| row.get(0))
+        .map_err(|e| format!("Failed to query resource [{}]: {}",
+                             resource.id, e)));
+    if unchanged > 0 {
+        return Ok(());
+    }
+
+    try!(remove(connection, &resource.id));
+    try!(connection.execute(
+        "INSERT INTO resources (id, url, labels) VALUES ($1, $2, $3)",
+        &[&resource.id, &resource.url, &labels])
+        .map_err(|e| format!("Failed to save resource [{}]: {}",
+                             resource.id, e)));
+    for (key, value) in &resource.labels {
+        try!(connection.execute(
+            "INSERT INTO labels (resource_id, key, value) VALUES ($1, $2, $3)",
+            &[&resource.id, key, value])
+            .map_err(|e| format!("Failed to save labels of [{}]: {}",
+                                 resource.id, e)));
+    }
+    Ok(())
+}
+
+/// Removes the resource with the id and its labels, returning whether one
+/// was stored.
+fn remove(connection: &Connection, id: &str) -> Result<bool, String> {
+    try!(connection.execute("DELETE FROM labels WHERE resource_id = $1",
+                            &[&id])
+        .map_err(|e| format!("Failed to remove labels of [{}]: {}", id, e)));
+    connection.execute("DELETE FROM resources WHERE id = $1", &[&id])
+        .map(|removed| removed > 0)
+        .map_err(|e| format!("Failed to remove resource [{}]: {}", id, e))
+}
+
+/// Returns the ids of the stored resources that are not in `keep`.
+fn ids_except(connection: &Connection,
+              keep: &Resources) -> Result<Vec<String>, String> {
+    let keep: HashSet<&str> = keep.iter().map(|r| &r.id[..]).collect();
+    let mut statement = try!(connection.prepare("SELECT id FROM resources")
+        .map_err(|e| format!("Failed to query resource ids: {}", e)));
+    let ids: Vec<String> = try!(statement.query_map(&[], |row| row.get(0))
+        .map_err(|e| format!("Failed to query resource ids: {}", e)))
+        .filter_map(|id| id.ok())
+        .collect();
+    Ok(ids.into_iter()
+        .filter(|id| !keep.contains(&id[..]))
+        .collect())
+}
+
+/// Translates the selector into a condition on the rows of `resources` and
+/// the values of its parameters.
+fn selector_condition(selector: &LabelSelector) -> (String, Vec<String>) {
+    let mut terms = vec!("1".to_string());
+    let mut values = vec!();
+    for requirement in selector.requirements() {
+        let (negated, key, value) = match *requirement {
+            Requirement::Exists(ref key) => (false, key, None),
+            Requirement::Equals(ref key, ref value) =>
+                (false, key, Some(value)),
+            Requirement::NotEquals(ref key, ref value) =>
+                (true, key, Some(value)),
+        };
+        values.push(key.clone());
+        let mut term = format!(
+            "EXISTS (SELECT 1 FROM labels
+                     WHERE labels.resource_id = resources.id
+                     AND labels.key = ${}", values.len());
+        if let Some(value) = value {
+            values.push(value.clone());
+            term.push_str(&format!(" AND labels.value = ${}", values.len()));
+        }
+        term.push(')');
+        terms.push(if negated { format!("NOT {}", term) } else { term });
+    }
+    (terms.join(" AND "), values)
+}
+
+fn encode_labels(labels: &Labels) -> String {
+    // Encoding a map of strings cannot fail.
+    json::encode(labels).unwrap_or("{}".to_string())
+}
+
+fn decode_labels(raw: &str) -> Labels {
+    json::decode(raw).unwrap_or(Labels::new())
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::io::Read;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use common::http::{encode_segment, Connector};
+use common::net::{Resolution, Source};
+
+use hyper::Client;
+use hyper::header::Connection;
+use hyper::status::StatusCode;
+use rustc_serialize::json::Json;
+use threadpool::ThreadPool;
+use time;
+
+/// The number of traces kept by a `TraceLog`.
+pub const MAX_TRACES: usize = 100;
+
+/// The number of traces requested at once.
+const TRACE_THREADS: usize = 2;
+
+/// The number of traces that may be waiting or running at once.
+const MAX_PENDING_TRACES: usize = 16;
+
+/// How long to wait for a probe to connect and to finish a trace, which
+/// takes up to a second per hop.
+const TRACE_TIMEOUT_MS: u64 = 45000;
+
+/// A path trace that was requested from a probe because the reachability
+/// of one of its targets changed.
+#[derive(Clone, Debug)]
+pub struct TraceRecord {
+    /// Id of the resource (probe) that traced the path.
+    pub id: String,
+    pub target_url: String,
+    /// The reachability that the target changed to.
+    pub reachability: String,
+    /// When the trace was requested, in seconds since the epoch.
+    pub requested_at: i64,
+    /// The trace as returned by the probe, if it succeeded.
+    pub trace: Option<Json>,
+    pub error: Option<String>,
+}
+
+/// The most recent traces, newest first.
+pub struct TraceLog {
+    records: VecDeque<TraceRecord>,
+}
+
+impl TraceLog {
+    pub fn new() -> TraceLog {
+        TraceLog { records: VecDeque::new(), }
+    }
+
+    pub fn add(&mut self, record: TraceRecord) {
+        self.records.push_front(record);
+        self.records.truncate(MAX_TRACES);
+    }
+
+    pub fn records(&self) -> Vec<TraceRecord> {
+        self.records.iter().cloned().collect()
+    }
+}
+
+/// Returns the targets of a probe status whose reachability changed from
+/// the previous status to one other than reachable, along with their new
+/// reachability.
+pub fn transitions(previous: &Json, current: &Json) -> Vec<(String, String)> {
+    let previous_states = states(previous);
+    states(current).into_iter()
+        .filter(|&(ref url, ref state)| state != "reachable" &&
+                previous_states.iter().any(|&(ref u, ref s)|
+                    u == url && s != state))
+        .collect()
+}
+
+/// The reachability of each target of a probe status.
+fn states(status: &Json) -> Vec<(String, String)> {
+    let targets = match status.find_path(&["status", "targets"])
+        .and_then(|t| t.as_array()) {
+        Some(targets) => targets,
+        None => return vec!(),
+    };
+    targets.iter().filter_map(|target| {
+        let url = match target.find("url").and_then(|u| u.as_string()) {
+            Some(url) => url.to_string(),
+            None => return None,
+        };
+        let state = match target.find("reachability")
+            .and_then(|r| r.as_string()) {
+            Some(state) => state.to_string(),
+            None => match target.find("reachable")
+                .and_then(|r| r.as_boolean()) {
+                Some(true) => "reachable".to_string(),
+                _ => "unreachable".to_string(),
+            },
+        };
+        Some((url, state))
+    }).collect()
+}
+
+/// Asks the probe serving the resource URL to trace the path to one of its
+/// targets, via `POST /targets/{id}/trace`.
+pub fn request_trace(resource_url: &str,
+                     target_url: &str,
+                     resolution: &Resolution) -> Result<Json, String> {
+    let base_url = match resource_url.find("://") {
+        Some(index) => {
+            let rest = &resource_url[index + 3..];
+            let end = rest.find('/').unwrap_or(rest.len());
+            &resource_url[..index + 3 + end]
+        },
+        None => return Err(format!("Invalid resource URL [{}]", resource_url)),
+    };
+    let trace_url = format!("{}/targets/{}/trace",
+                            base_url,
+                            encode_segment(target_url));
+
+    let client = Client::with_connector(
+        Connector::new(None, Source::default(), resolution.clone())
+            .timeout(Duration::from_millis(TRACE_TIMEOUT_MS)));
+    let mut response = try!(client.post(&trace_url)
+        .header(Connection::close())
+        .send()
+        .map_err(|cause| format!("Failed to request [{}]: {}",
+                                 trace_url, cause)));
+
+    let mut body = String::new();
+    try!(response.read_to_string(&mut body)
+        .map_err(|cause| format!("Failed to read trace: {}", cause)));
+    if response.status != StatusCode::Ok {
+        return Err(format!("[{}] returned [{}]: {}",
+                           trace_url, response.status, body));
+    }
+    Json::from_str(&body)
+        .map(|json| json.find("trace").cloned().unwrap_or(json))
+        .map_err(|cause| format!("Failed to parse trace: {}", cause))
+}
+
+/// Requests traces from probes on a small pool of threads of its own, so
+/// that slow traces do not hold up fetching, and records the results in a
+/// trace log.  At most `TRACE_THREADS` traces run at once; transitions
+/// beyond `MAX_PENDING_TRACES` waiting or running are recorded as skipped.
+pub struct Tracer {
+    pool: Mutex<ThreadPool>,
+    pending: Arc<AtomicUsize>,
+    trace_log: Arc<RwLock<TraceLog>>,
+    resolution: Resolution,
+}
+
+impl Tracer {
+    pub fn new(trace_log: Arc<RwLock<TraceLog>>, resolution: Resolution)
+               -> Tracer {
+        Tracer {
+            pool: Mutex::new(ThreadPool::new(TRACE_THREADS)),
+            pending: Arc::new(AtomicUsize::new(0)),
+            trace_log: trace_log,
+            resolution: resolution,
+        }
+    }
+
+    /// Requests a trace for every target that changed reachability.
+    pub fn trace_transitions(&self,
+                             id: &str,
+                             resource_url: &str,
+                             previous: &Json,
+                             current: &Json) {
+        for (target_url, state) in transitions(previous, current) {
+            info!("Target [{}] of resource [{}] became {}; requesting a \
+                   trace",
+                  target_url, id, state);
+            let record = TraceRecord {
+                id: id.to_string(),
+                target_url: target_url,
+                reachability: state,
+                requested_at: time::get_time().sec,
+                trace: None,
+                error: None,
+            };
+
+            if self.pending.fetch_add(1, Ordering::SeqCst) >=
+                MAX_PENDING_TRACES {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+                warn!("Too many traces pending; skipped tracing [{}]",
+                      record.target_url);
+                self.trace_log.write().unwrap().add(TraceRecord {
+                    error: Some("Skipped, as too many traces were pending"
+                                .to_string()),
+                    ..record
+                });
+                continue;
+            }
+
+            let resource_url = resource_url.to_string();
+            let pending = self.pending.clone();
+            let trace_log = self.trace_log.clone();
+            let resolution = self.resolution.clone();
+            self.pool.lock().unwrap().execute(move || {
+                let result = request_trace(&resource_url,
+                                           &record.target_url,
+                                           &resolution);
+                if let Err(ref cause) = result {
+                    warn!("{}", cause);
+                }
+                trace_log.write().unwrap().add(TraceRecord {
+                    trace: result.as_ref().ok().cloned(),
+                    error: result.err(),
+                    ..record
+                });
+                pending.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::thread;
+use std::time::SystemTime;
+
+use collect::discovery::sync_resources;
+use collect::resource::{read_resources_file, Resources, ResourceStore};
+
+use libc;
+use mio::{EventLoop, Handler};
+
+static RELOAD_REQUESTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+extern "C" fn request_reload(_: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Watches the resources file, reconciling the resource store with its
+/// contents whenever the file is modified or the process receives SIGHUP.
+///
+/// Only resources that were loaded from the file are removed when they
+/// disappear from it; resources added via the REST API are left alone.
+pub fn start_file_watcher(path: String,
+                          poll_ms: u64,
+                          loaded: Resources,
+                          resource_store: Arc<RwLock<ResourceStore>>) {
+    info!("Watching resources file [{}]", path);
+    unsafe {
+        libc::signal(libc::SIGHUP,
+                     request_reload as extern "C" fn(libc::c_int)
+                         as libc::sighandler_t);
+    }
+
+    let mut event_loop = EventLoop::new().unwrap();
+    let _ = event_loop.timeout_ms((), poll_ms);
+    thread::spawn(move || {
+        let _ = event_loop.run(&mut WatcherHandler {
+            modified: modified_time(&path),
+            path: path,
+            poll_ms: poll_ms,
+            loaded: loaded,
+            resource_store: resource_store,
+        });
+    });
+}
+
+struct WatcherHandler {
+    path: String,
+    poll_ms: u64,
+    modified: Option<SystemTime>,
+    loaded: Resources,
+    resource_store: Arc<RwLock<ResourceStore>>,
+}
+
+impl WatcherHandler {
+    fn reload(&mut self) {
+        let resources = match read_resources_file(&self.path) {
+            Ok(resources) => resources,
+            Err(cause) => {
+                warn!("Keeping previous resources: {}", cause);
+                return;
+            },
+        };
+
+        info!("Reloaded resources file [{}]: {} resources",
+              self.path,
+              resources.len());
+
+        sync_resources(&self.resource_store, &self.loaded, &resources);
+        self.loaded = resources;
+    }
+}
+
+impl Handler for WatcherHandler {
+    type Timeout = ();
+    type Message = ();
+
+    fn timeout(&mut self,
+               event_loop: &mut EventLoop<WatcherHandler>,
+               _: ()) {
+        let modified = modified_time(&self.path);
+        let signalled = RELOAD_REQUESTED.swap(false, Ordering::SeqCst);
+
+        if signalled {
+            info!("Received SIGHUP, reloading resources file");
+        }
+        if signalled || modified != self.modified {
+            self.modified = modified;
+            self.reload();
+        }
+        let _ = event_loop.timeout_ms((), self.poll_ms);
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
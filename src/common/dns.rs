@@ -0,0 +1,680 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream,
+    UdpSocket};
+use std::time::Duration;
+
+use common::net::{join_host_port, split_host_port};
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+/// The header flag of answers that did not fit in a UDP datagram.
+const FLAG_TC: u16 = 0x0200;
+
+const RESOLV_CONF: &'static str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+const DEFAULT_TIMEOUT_MS: u64 = 2000;
+
+/// Prefix of target URLs that are expanded using DNS SRV records, e.g:
+/// `srv+http://_probe._tcp.star.marathon.mesos/status`
+pub const SRV_PREFIX: &'static str = "srv+";
+
+/// Prefix of target URLs that are expanded using DNS A and AAAA records,
+/// e.g: `dns+http://star.example.com:9000/status`
+pub const A_PREFIX: &'static str = "dns+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// A minimal stub resolver that sends queries over UDP to a single
+/// nameserver, and repeats them over TCP if the answer is truncated.
+#[derive(Clone, Debug)]
+pub struct Resolver {
+    server: SocketAddr,
+    timeout_ms: u64,
+}
+
+impl Resolver {
+    pub fn new(server: SocketAddr) -> Resolver {
+        Resolver { server: server, timeout_ms: DEFAULT_TIMEOUT_MS, }
+    }
+
+    /// Uses the first nameserver listed in `/etc/resolv.conf`.
+    pub fn system() -> Result<Resolver, String> {
+        let mut raw = String::new();
+        if let Err(cause) = File::open(RESOLV_CONF)
+            .and_then(|mut file| file.read_to_string(&mut raw)) {
+            return Err(format!("Failed to read [{}]: {}", RESOLV_CONF, cause));
+        }
+
+        let server = raw.lines()
+            .map(|line| line.trim())
+            .filter(|line| line.starts_with("nameserver"))
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .filter_map(|address| address.parse::<IpAddr>().ok())
+            .next();
+
+        match server {
+            Some(ip) => Ok(Resolver::new(SocketAddr::new(ip, DNS_PORT))),
+            None => Err(format!("No nameserver found in [{}]", RESOLV_CONF)),
+        }
+    }
+
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    pub fn resolve_srv(&self, name: &str) -> Result<Vec<SrvRecord>, String> {
+        let answers = try!(self.query(name, TYPE_SRV));
+        let mut records = vec!();
+        for answer in answers {
+            if let Rdata::Srv(record) = answer {
+                records.push(record);
+            }
+        }
+        records.sort_by(|a, b|
+            (a.priority, a.target.clone(), a.port)
+                .cmp(&(b.priority, b.target.clone(), b.port)));
+        Ok(records)
+    }
+
+    pub fn resolve_ipv4(&self, name: &str) -> Result<Vec<IpAddr>, String> {
+        self.resolve_ips(name, TYPE_A)
+    }
+
+    pub fn resolve_ipv6(&self, name: &str) -> Result<Vec<IpAddr>, String> {
+        self.resolve_ips(name, TYPE_AAAA)
+    }
+
+    fn resolve_ips(&self, name: &str, qtype: u16)
+                   -> Result<Vec<IpAddr>, String> {
+        // Literal addresses resolve to themselves.
+        if let Ok(ip) = name.parse::<IpAddr>() {
+            return Ok(vec!(ip));
+        }
+        let answers = try!(self.query(name, qtype));
+        let mut ips = vec!();
+        for answer in answers {
+            if let Rdata::Ip(ip) = answer {
+                ips.push(ip);
+            }
+        }
+        ips.sort();
+        Ok(ips)
+    }
+
+    fn query(&self, name: &str, qtype: u16) -> Result<Vec<Rdata>, String> {
+        let id = (::time::precise_time_ns() & 0xffff) as u16;
+        let request = try!(encode_query(id, name, qtype));
+
+        let answer = try!(self.exchange_udp(&request, id, name));
+        if read_u16(&answer, 2) & FLAG_TC == 0 {
+            return decode_answers(&answer, name);
+        }
+        // The answer did not fit in a datagram.
+        let answer = try!(self.exchange_tcp(&request, id, name));
+        decode_answers(&answer, name)
+    }
+
+    fn exchange_udp(&self, request: &[u8], id: u16, name: &str)
+                    -> Result<Vec<u8>, String> {
+        let bind_addr = match self.server {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = try!(UdpSocket::bind(bind_addr)
+            .map_err(|e| format!("Failed to bind DNS socket: {}", e)));
+        let _ = socket.set_read_timeout(
+            Some(Duration::from_millis(self.timeout_ms)));
+
+        try!(socket.send_to(request, self.server)
+            .map_err(|e| format!("Failed to query [{}]: {}", self.server, e)));
+
+        let mut buf = [0u8; 4096];
+        loop {
+            let (len, from) = try!(socket.recv_from(&mut buf)
+                .map_err(|e| format!("No answer from [{}] for [{}]: {}",
+                                     self.server, name, e)));
+            if from != self.server || len < 12 || read_u16(&buf, 0) != id {
+                continue; // Not the answer we are waiting for.
+            }
+            return Ok(buf[..len].to_vec());
+        }
+    }
+
+    /// Sends the query over TCP, where each message is prefixed by its
+    /// length.
+    fn exchange_tcp(&self, request: &[u8], id: u16, name: &str)
+                    -> Result<Vec<u8>, String> {
+        let failed = |e: io::Error| format!("No answer from [{}] for [{}] \
+                                             over TCP: {}",
+                                            self.server, name, e);
+        let timeout = Duration::from_millis(self.timeout_ms);
+        let mut stream = try!(TcpStream::connect_timeout(&self.server, timeout)
+            .map_err(&failed));
+        let _ = stream.set_read_timeout(Some(timeout));
+        let _ = stream.set_write_timeout(Some(timeout));
+
+        let mut message = vec!();
+        push_u16(&mut message, request.len() as u16);
+        message.extend(request.iter().cloned());
+        try!(stream.write_all(&message).map_err(&failed));
+
+        let mut length = [0u8; 2];
+        try!(stream.read_exact(&mut length).map_err(&failed));
+        let mut answer = vec!(0u8; read_u16(&length, 0) as usize);
+        try!(stream.read_exact(&mut answer).map_err(&failed));
+        if answer.len() < 12 || read_u16(&answer, 0) != id {
+            return Err(format!("Invalid answer from [{}] for [{}] over TCP",
+                               self.server, name));
+        }
+        Ok(answer)
+    }
+}
+
+/// Parses the address of a nameserver, with an optional port, e.g.
+/// `10.0.0.2`, `10.0.0.2:5353` or `[fd00::2]:53`.
+pub fn parse_nameserver(address: &str) -> Result<SocketAddr, String> {
+    let (host, port) = try!(split_host_port(address));
+    match host.parse::<IpAddr>() {
+        Ok(ip) => Ok(SocketAddr::new(ip, port.unwrap_or(DNS_PORT))),
+        Err(_) => Err(format!("Invalid nameserver address [{}]", address)),
+    }
+}
+
+/// A concrete URL discovered by expanding a discovery URL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveredUrl {
+    /// The `host:port` of the discovered endpoint.
+    pub endpoint: String,
+    pub url: String,
+}
+
+/// Returns whether the URL must be expanded using DNS before probing.
+pub fn is_discovery_url(url: &str) -> bool {
+    url.starts_with(SRV_PREFIX) || url.starts_with(A_PREFIX)
+}
+
+/// Expands a discovery URL into one concrete URL per discovered endpoint.
+pub fn expand_url(url: &str, resolver: &Resolver)
+                  -> Result<Vec<DiscoveredUrl>, String> {
+    let (prefix, rest) =
+        if url.starts_with(SRV_PREFIX) {
+            (SRV_PREFIX, &url[SRV_PREFIX.len()..])
+        } else if url.starts_with(A_PREFIX) {
+            (A_PREFIX, &url[A_PREFIX.len()..])
+        } else {
+            return Err(format!("Not a discovery URL [{}]", url));
+        };
+
+    let scheme_end = match rest.find("://") {
+        Some(index) => index + 3,
+        None => return Err(format!("Invalid discovery URL [{}]", url)),
+    };
+    let (scheme, rest) = rest.split_at(scheme_end);
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+
+    let endpoints: Vec<(String, u16)> =
+        if prefix == SRV_PREFIX {
+            try!(resolver.resolve_srv(authority)).into_iter()
+                .map(|record| (record.target, record.port))
+                .collect()
+        } else {
+            let (host, port) = match split_host_port(authority) {
+                Ok((host, Some(port))) => (host, port),
+                Ok((_, None)) => return Err(
+                    format!("Missing port in discovery URL [{}]", url)),
+                Err(_) => return Err(
+                    format!("Invalid port in discovery URL [{}]", url)),
+            };
+            try!(resolve_dual_stack(resolver, &host)).into_iter()
+                .map(|ip| (format!("{}", ip), port))
+                .collect()
+        };
+
+    Ok(endpoints.into_iter()
+        .map(|(host, port)| {
+            let endpoint = join_host_port(&host, port);
+            DiscoveredUrl {
+                url: format!("{}{}{}", scheme, endpoint, path),
+                endpoint: endpoint,
+            }
+        })
+        .collect())
+}
+
+/// Resolves both the A and AAAA records of the name, failing only if
+/// neither can be resolved.
+pub fn resolve_dual_stack(resolver: &Resolver, name: &str)
+                      -> Result<Vec<IpAddr>, String> {
+    match (resolver.resolve_ipv4(name), resolver.resolve_ipv6(name)) {
+        (Err(cause), Err(_)) => Err(cause),
+        (ipv4, ipv6) => {
+            let mut ips = ipv4.unwrap_or(vec!());
+            ips.extend(ipv6.unwrap_or(vec!()));
+            ips.dedup();
+            Ok(ips)
+        },
+    }
+}
+
+enum Rdata {
+    Ip(IpAddr),
+    Srv(SrvRecord),
+    Other,
+}
+
+fn encode_query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>, String> {
+    let mut packet = vec!();
+    push_u16(&mut packet, id);
+    push_u16(&mut packet, 0x0100); // Standard query, recursion desired.
+    push_u16(&mut packet, 1);      // One question.
+    push_u16(&mut packet, 0);
+    push_u16(&mut packet, 0);
+    push_u16(&mut packet, 0);
+
+    for label in name.trim_right_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("Invalid DNS name [{}]", name));
+        }
+        packet.push(label.len() as u8);
+        packet.extend(label.as_bytes().iter().cloned());
+    }
+    packet.push(0);
+    push_u16(&mut packet, qtype);
+    push_u16(&mut packet, CLASS_IN);
+    Ok(packet)
+}
+
+fn decode_answers(packet: &[u8], name: &str) -> Result<Vec<Rdata>, String> {
+    let truncated = || format!("Truncated DNS answer for [{}]", name);
+
+    let flags = read_u16(packet, 2);
+    if flags & FLAG_TC != 0 {
+        return Err(truncated());
+    }
+    match flags & 0x000f {
+        0 => (),
+        3 => return Err(format!("No such DNS name [{}]", name)),
+        rcode => return Err(
+            format!("DNS query for [{}] failed with rcode {}", name, rcode)),
+    }
+
+    let questions = read_u16(packet, 4);
+    let answers = read_u16(packet, 6);
+    let mut offset = 12;
+
+    for _ in 0..questions {
+        let (_, next) = try!(read_name(packet, offset).ok_or_else(&truncated));
+        offset = next + 4;
+    }
+
+    let mut result = vec!();
+    for _ in 0..answers {
+        let (_, next) = try!(read_name(packet, offset).ok_or_else(&truncated));
+        if next + 10 > packet.len() {
+            return Err(truncated());
+        }
+        let rtype = read_u16(packet, next);
+        let length = read_u16(packet, next + 8) as usize;
+        let start = next + 10;
+        if start + length > packet.len() {
+            return Err(truncated());
+        }
+        let data = &packet[start..start + length];
+
+        let rdata = match (rtype, length) {
+            (TYPE_A, 4) => Rdata::Ip(IpAddr::V4(
+                Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+            (TYPE_AAAA, 16) => {
+                let mut segments = [0u16; 8];
+                for i in 0..8 {
+                    segments[i] = read_u16(data, i * 2);
+                }
+                Rdata::Ip(IpAddr::V6(Ipv6Addr::new(
+                    segments[0], segments[1], segments[2], segments[3],
+                    segments[4], segments[5], segments[6], segments[7])))
+            },
+            (TYPE_SRV, _) if length > 6 => {
+                let (target, _) = try!(read_name(packet, start + 6)
+                    .ok_or_else(&truncated));
+                Rdata::Srv(SrvRecord {
+                    priority: read_u16(data, 0),
+                    weight: read_u16(data, 2),
+                    port: read_u16(data, 4),
+                    target: target,
+                })
+            },
+            _ => Rdata::Other,
+        };
+        result.push(rdata);
+        offset = start + length;
+    }
+    Ok(result)
+}
+
+/// Reads a possibly compressed name, returning it along with the offset
+/// just past its encoding at the supplied offset.
+fn read_name(packet: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = vec!();
+    let mut position = offset;
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let length = match packet.get(position) {
+            Some(&length) => length as usize,
+            None => return None,
+        };
+        if length == 0 {
+            if end.is_none() { end = Some(position + 1); }
+            break;
+        }
+        if length & 0xc0 == 0xc0 {
+            let low = match packet.get(position + 1) {
+                Some(&low) => low as usize,
+                None => return None,
+            };
+            if end.is_none() { end = Some(position + 2); }
+            jumps += 1;
+            if jumps > 16 { return None; }
+            position = ((length & 0x3f) << 8) | low;
+            continue;
+        }
+        if position + 1 + length > packet.len() {
+            return None;
+        }
+        let label = &packet[position + 1..position + 1 + length];
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        position += 1 + length;
+    }
+
+    end.map(|end| (labels.join("."), end))
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    ((buf[offset] as u16) << 8) | (buf[offset + 1] as u16)
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.push((value >> 8) as u8);
+    buf.push((value & 0xff) as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use super::{CLASS_IN, FLAG_TC, TYPE_A, TYPE_AAAA, TYPE_SRV};
+    use super::{Rdata, decode_answers, encode_query, push_u16, read_name,
+                read_u16};
+
+    /// Encodes the rdata of an SRV record with an uncompressed target.
+    fn srv(priority: u16, weight: u16, port: u16, target: &str) -> Vec<u8> {
+        let mut data = vec!();
+        push_u16(&mut data, priority);
+        push_u16(&mut data, weight);
+        push_u16(&mut data, port);
+        for label in target.split('.') {
+            data.push(label.len() as u8);
+            data.extend(label.as_bytes().iter().cloned());
+        }
+        data.push(0);
+        data
+    }
+
+    /// Builds the response to a query, with one answer per record. Answer
+    /// names are compressed to point at the question.
+    fn response(query: &[u8], rcode: u16, records: &[(u16, Vec<u8>)])
+                -> Vec<u8> {
+        let mut packet = vec!();
+        packet.extend(query[..2].iter().cloned());
+        push_u16(&mut packet, 0x8180 | rcode);
+        push_u16(&mut packet, 1);
+        push_u16(&mut packet, records.len() as u16);
+        push_u16(&mut packet, 0);
+        push_u16(&mut packet, 0);
+        packet.extend(query[12..].iter().cloned());
+        for &(rtype, ref data) in records {
+            push_u16(&mut packet, 0xc00c);
+            push_u16(&mut packet, rtype);
+            push_u16(&mut packet, CLASS_IN);
+            push_u16(&mut packet, 0);
+            push_u16(&mut packet, 60);
+            push_u16(&mut packet, data.len() as u16);
+            packet.extend(data.iter().cloned());
+        }
+        packet
+    }
+
+    /// Starts a nameserver on the loopback interface that answers each
+    /// query with the records `answer` returns for its name and type.
+    fn stub_server<F>(answer: F) -> SocketAddr
+        where F: Fn(&str, u16) -> Vec<(u16, Vec<u8>)> + Send + 'static {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((len, from)) = socket.recv_from(&mut buf) {
+                let query = &buf[..len];
+                let (name, end) = read_name(query, 12).unwrap();
+                let records = answer(&name, read_u16(query, end));
+                let rcode = if records.is_empty() { 3 } else { 0 };
+                let packet = response(query, rcode, &records);
+                let _ = socket.send_to(&packet, from);
+            }
+        });
+        address
+    }
+
+    fn example_server() -> SocketAddr {
+        stub_server(|name, qtype| match (name, qtype) {
+            ("_star._tcp.example.com", TYPE_SRV) => vec!(
+                (TYPE_SRV, srv(20, 0, 9002, "b.example.com")),
+                (TYPE_SRV, srv(10, 0, 9001, "a.example.com"))),
+            ("a.example.com", TYPE_A) => vec!(
+                (TYPE_A, vec!(10, 0, 0, 2)),
+                (TYPE_A, vec!(10, 0, 0, 1))),
+            ("a.example.com", TYPE_AAAA) => vec!(
+                (TYPE_AAAA, vec!(0xfd, 0, 0, 0, 0, 0, 0, 0,
+                                 0, 0, 0, 0, 0, 0, 0, 1))),
+            _ => vec!(),
+        })
+    }
+
+    #[test]
+    fn encode_query_layout() {
+        let packet = encode_query(0x1234, "a.example.com.", TYPE_A).unwrap();
+        let mut expected = vec!(0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0,
+                                0);
+        expected.extend(b"\x01a\x07example\x03com\x00".iter().cloned());
+        expected.extend([0, 1, 0, 1].iter().cloned());
+        assert_eq!(packet, expected);
+    }
+
+    #[test]
+    fn encode_query_rejects_invalid_names() {
+        assert!(encode_query(1, "a..example.com", TYPE_A).is_err());
+        assert!(encode_query(1, "", TYPE_A).is_err());
+        let label: String = ::std::iter::repeat('x').take(64).collect();
+        let long = format!("{}.example.com", label);
+        assert!(encode_query(1, &long, TYPE_A).is_err());
+    }
+
+    #[test]
+    fn decode_compressed_answers() {
+        let query = encode_query(7, "a.example.com", TYPE_SRV).unwrap();
+        let mut ipv6 = vec!(0x20, 0x01, 0x0d, 0xb8);
+        ipv6.extend([0u8; 11].iter().cloned());
+        ipv6.push(1);
+        let packet = response(&query, 0, &[
+            (TYPE_A, vec!(10, 0, 0, 1)),
+            (TYPE_AAAA, ipv6),
+            (TYPE_SRV, {
+                // The target points at the name in the question.
+                let mut data = vec!(0, 10, 0, 5, 0x23, 0x29);
+                data.extend([0xc0, 12].iter().cloned());
+                data
+            }),
+            (99, vec!(1, 2, 3)),
+        ]);
+
+        let answers = decode_answers(&packet, "a.example.com").unwrap();
+        assert_eq!(answers.len(), 4);
+        match answers[0] {
+            Rdata::Ip(ip) => assert_eq!(format!("{}", ip), "10.0.0.1"),
+            _ => panic!("Expected an A record"),
+        }
+        match answers[1] {
+            Rdata::Ip(ip) => assert_eq!(format!("{}", ip), "2001:db8::1"),
+            _ => panic!("Expected an AAAA record"),
+        }
+        match answers[2] {
+            Rdata::Srv(ref record) => assert_eq!(*record, SrvRecord {
+                priority: 10,
+                weight: 5,
+                port: 9001,
+                target: "a.example.com".to_string(),
+            }),
+            _ => panic!("Expected an SRV record"),
+        }
+        match answers[3] {
+            Rdata::Other => (),
+            _ => panic!("Expected an unknown record"),
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let query = encode_query(7, "missing.example.com", TYPE_A).unwrap();
+        let packet = response(&query, 3, &[]);
+        let error = decode_answers(&packet, "missing.example.com").err();
+        assert_eq!(error,
+                   Some("No such DNS name [missing.example.com]".to_string()));
+
+        let packet = response(&query, 2, &[]);
+        assert!(decode_answers(&packet, "missing.example.com").is_err());
+
+        let packet = response(&query, 0, &[(TYPE_A, vec!(10, 0, 0, 1))]);
+        let truncated = &packet[..packet.len() - 2];
+        assert!(decode_answers(truncated, "missing.example.com").is_err());
+
+        let mut packet = response(&query, 0, &[]);
+        packet[2] |= (FLAG_TC >> 8) as u8;
+        assert!(decode_answers(&packet, "missing.example.com").is_err());
+    }
+
+    #[test]
+    fn read_name_rejects_pointer_loops() {
+        let packet = [0xc0, 0x00];
+        assert!(read_name(&packet, 0).is_none());
+    }
+
+    #[test]
+    fn parse_nameservers() {
+        assert_eq!(parse_nameserver("10.0.0.2"),
+                   Ok("10.0.0.2:53".parse().unwrap()));
+        assert_eq!(parse_nameserver("10.0.0.2:5353"),
+                   Ok("10.0.0.2:5353".parse().unwrap()));
+        assert_eq!(parse_nameserver("[fd00::2]:53"),
+                   Ok("[fd00::2]:53".parse().unwrap()));
+        assert!(parse_nameserver("ns.example.com").is_err());
+    }
+
+    #[test]
+    fn resolve_with_stub_server() {
+        let resolver = Resolver::new(example_server());
+
+        let records = resolver.resolve_srv("_star._tcp.example.com").unwrap();
+        let targets: Vec<(String, u16)> = records.into_iter()
+            .map(|record| (record.target, record.port))
+            .collect();
+        assert_eq!(targets, vec!(("a.example.com".to_string(), 9001),
+                                 ("b.example.com".to_string(), 9002)));
+
+        assert_eq!(resolver.resolve_ipv4("a.example.com").unwrap(),
+                   vec!("10.0.0.1".parse::<IpAddr>().unwrap(),
+                        "10.0.0.2".parse::<IpAddr>().unwrap()));
+        assert_eq!(resolver.resolve_ipv4("10.1.1.1").unwrap(),
+                   vec!("10.1.1.1".parse::<IpAddr>().unwrap()));
+        assert!(resolver.resolve_ipv4("missing.example.com").is_err());
+    }
+
+    #[test]
+    fn resolve_truncated_answers_over_tcp() {
+        // The nameserver only answers in full over TCP, on the same port.
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(address).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            if let Ok((len, from)) = udp.recv_from(&mut buf) {
+                let mut packet = response(&buf[..len], 0, &[]);
+                packet[2] |= (FLAG_TC >> 8) as u8;
+                let _ = udp.send_to(&packet, from);
+            }
+        });
+        thread::spawn(move || {
+            if let Ok((mut stream, _)) = tcp.accept() {
+                let mut length = [0u8; 2];
+                stream.read_exact(&mut length).unwrap();
+                let mut query = vec!(0u8; read_u16(&length, 0) as usize);
+                stream.read_exact(&mut query).unwrap();
+                let packet =
+                    response(&query, 0, &[(TYPE_A, vec!(10, 0, 0, 1))]);
+                let mut message = vec!();
+                push_u16(&mut message, packet.len() as u16);
+                message.extend(packet);
+                stream.write_all(&message).unwrap();
+            }
+        });
+
+        let resolver = Resolver::new(address);
+        assert_eq!(resolver.resolve_ipv4("a.example.com").unwrap(),
+                   vec!("10.0.0.1".parse::<IpAddr>().unwrap()));
+    }
+
+    #[test]
+    fn expand_urls_with_stub_server() {
+        let resolver = Resolver::new(example_server());
+
+        let urls = expand_url("srv+http://_star._tcp.example.com/status",
+                              &resolver).unwrap();
+        assert_eq!(urls, vec!(
+            DiscoveredUrl {
+                endpoint: "a.example.com:9001".to_string(),
+                url: "http://a.example.com:9001/status".to_string(),
+            },
+            DiscoveredUrl {
+                endpoint: "b.example.com:9002".to_string(),
+                url: "http://b.example.com:9002/status".to_string(),
+            }));
+
+        let urls = expand_url("dns+tcp://a.example.com:9000", &resolver)
+            .unwrap();
+        let urls: Vec<String> = urls.into_iter().map(|u| u.url).collect();
+        assert_eq!(urls, vec!("tcp://10.0.0.1:9000".to_string(),
+                              "tcp://10.0.0.2:9000".to_string(),
+                              "tcp://[fd00::1]:9000".to_string()));
+
+        assert!(expand_url("dns+tcp://a.example.com", &resolver).is_err());
+        assert!(expand_url("http://a.example.com", &resolver).is_err());
+        assert!(!is_discovery_url("http://a.example.com"));
+    }
+}
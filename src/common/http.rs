@@ -0,0 +1,269 @@
+use std::io;
+use std::net::IpAddr;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::net::{self, join_host_port, unbracket_host, Family, Resolution,
+    Source};
+
+use hyper;
+use hyper::{Client, Server};
+use hyper::net::{Fresh, HttpStream, HttpsStream, NetworkConnector, Openssl,
+    Ssl};
+use hyper::server::{Request, Response};
+use time;
+use url::form_urlencoded;
+
+pub type Query = Vec<(String, String)>;
+
+/// How long clients wait by default to connect, and for each read or write
+/// once connected.
+pub const DEFAULT_TIMEOUT_MS: u64 = 10000;
+
+/// Splits a request path into the bare path and its decoded query
+/// parameters, e.g. `/resources?selector=app%3Dweb` yields
+/// `("/resources", [("selector", "app=web")])`.
+pub fn split_query(abs_path: &str) -> (&str, Query) {
+    match abs_path.find('?') {
+        Some(index) => {
+            let query = &abs_path[index + 1..];
+            (&abs_path[..index], form_urlencoded::parse(query.as_bytes()))
+        },
+        None => (abs_path, vec!()),
+    }
+}
+
+/// Returns the value of the first query parameter with the supplied name.
+pub fn query_param<'a>(query: &'a Query, name: &str) -> Option<&'a str> {
+    query.iter()
+        .find(|&&(ref key, _)| key == name)
+        .map(|&(_, ref value)| &value[..])
+}
+
+/// Percent-encodes every byte of the text except unreserved characters, so
+/// that e.g. a URL can be used as a single path segment.
+pub fn encode_segment(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' |
+            b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decodes a percent-encoded path segment.
+pub fn decode_segment(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = vec!();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+        } else {
+            None
+        };
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Serves HTTP requests with the handler on each of the addresses, all on
+/// the same port, and blocks until the servers stop.  IPv6 addresses are
+/// bracketed as needed.
+pub fn serve<H>(addresses: &[String], port: u16, handler: H)
+    where H: Fn(Request, Response<Fresh>) + Send + Sync + 'static {
+    let handler = Arc::new(handler);
+    let servers: Vec<_> = addresses.iter().map(|address| {
+        let bind_addr = join_host_port(address, port);
+        let handler = handler.clone();
+        let serve = move |req: Request, res: Response<Fresh>| {
+            handler(req, res);
+        };
+        info!("Starting HTTP server on [{}]", bind_addr);
+        Server::http(&bind_addr[..])
+            .and_then(|s| s.handle(serve))
+            .unwrap_or_else(|cause|
+                panic!("Failed to listen on [{}]: {}", bind_addr, cause))
+    }).collect();
+
+    // Dropping each server waits for it to stop.
+    drop(servers);
+}
+
+/// When each phase of establishing a connection ended, as
+/// `time::precise_time_ns()` readings taken by a `Connector`.  The phases
+/// a failed connection did not complete are `None`.
+#[derive(Clone, Debug)]
+pub struct ConnectTiming {
+    /// When the connector started resolving the host.
+    pub start_ns: u64,
+    pub resolved_ns: Option<u64>,
+    /// When the TCP connection was established.
+    pub connected_ns: Option<u64>,
+    /// When the TLS handshake completed, for `https://` URLs.
+    pub tls_ns: Option<u64>,
+    /// When the connection was ready for the request.
+    pub ready_ns: Option<u64>,
+    /// The address the connection left from.
+    pub local_ip: Option<IpAddr>,
+    /// The address the connection was made to, or if connecting failed,
+    /// the last address attempted.
+    pub remote_ip: Option<IpAddr>,
+}
+
+impl ConnectTiming {
+    fn new(start_ns: u64) -> ConnectTiming {
+        ConnectTiming {
+            start_ns: start_ns,
+            resolved_ns: None,
+            connected_ns: None,
+            tls_ns: None,
+            ready_ns: None,
+            local_ip: None,
+            remote_ip: None,
+        }
+    }
+}
+
+/// Connects like hyper's default connector, but accepts the bracketed IPv6
+/// hosts of URLs such as `http://[fd00::1]:9000/status`, may be limited to
+/// the addresses of one family, may connect from a particular source and
+/// may resolve hosts in a particular way.  Connecting to each address, and
+/// each read and write once connected, time out after `DEFAULT_TIMEOUT_MS`
+/// unless set otherwise.
+pub struct Connector {
+    ssl: Openssl,
+    family: Option<Family>,
+    source: Source,
+    resolution: Resolution,
+    connect_timeout: Duration,
+    read_timeout: Option<Duration>,
+    timing: Option<Arc<Mutex<Option<ConnectTiming>>>>,
+}
+
+impl Connector {
+    pub fn new(family: Option<Family>,
+               source: Source,
+               resolution: Resolution) -> Connector {
+        let timeout = Duration::from_millis(DEFAULT_TIMEOUT_MS);
+        Connector {
+            ssl: Openssl::default(),
+            family: family,
+            source: source,
+            resolution: resolution,
+            connect_timeout: timeout,
+            read_timeout: Some(timeout),
+            timing: None,
+        }
+    }
+
+    /// Times out connecting, reading and writing after the duration.
+    pub fn timeout(mut self, timeout: Duration) -> Connector {
+        self.connect_timeout = timeout;
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Times out reads and writes after the duration, or never, e.g. for
+    /// the events of a long-lived stream.
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Connector {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Records the timing of each connection in `timing`, e.g. to report
+    /// where the time of a request went.  Connections that fail record the
+    /// phases they completed.
+    pub fn capture_timing(mut self,
+                          timing: Arc<Mutex<Option<ConnectTiming>>>)
+                          -> Connector {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Connects, recording the end of each phase in `timing` as it
+    /// completes.
+    fn connect_timed(&self,
+                     host: &str,
+                     port: u16,
+                     scheme: &str,
+                     timing: &mut ConnectTiming)
+                     -> hyper::Result<HttpsStream<<Openssl as Ssl>::Stream>> {
+        let addrs =
+            try!(net::resolve(host, port, self.family, &self.resolution));
+        timing.resolved_ns = Some(time::precise_time_ns());
+
+        let timeout = Some(self.connect_timeout);
+        let stream = match net::connect(&addrs, &self.source, timeout) {
+            Ok(stream) => stream,
+            Err(cause) => {
+                // The failure is that of the last address attempted.
+                timing.remote_ip = addrs.last().map(|addr| addr.ip());
+                return Err(hyper::Error::from(cause));
+            },
+        };
+        try!(stream.set_read_timeout(self.read_timeout));
+        try!(stream.set_write_timeout(self.read_timeout));
+        timing.local_ip = stream.local_addr().ok().map(|addr| addr.ip());
+        timing.remote_ip = stream.peer_addr().ok().map(|addr| addr.ip());
+        let stream = HttpStream(stream);
+        timing.connected_ns = Some(time::precise_time_ns());
+
+        let stream = match scheme {
+            "http" => HttpsStream::Http(stream),
+            "https" => {
+                let stream =
+                    try!(self.ssl.wrap_client(stream, unbracket_host(host)));
+                timing.tls_ns = Some(time::precise_time_ns());
+                HttpsStream::Https(stream)
+            },
+            _ => return Err(hyper::Error::from(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid scheme for HTTP [{}]", scheme)))),
+        };
+        timing.ready_ns = Some(time::precise_time_ns());
+        Ok(stream)
+    }
+}
+
+impl NetworkConnector for Connector {
+    type Stream = HttpsStream<<Openssl as Ssl>::Stream>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str)
+               -> hyper::Result<Self::Stream> {
+        let mut timing = ConnectTiming::new(time::precise_time_ns());
+        let stream = self.connect_timed(host, port, scheme, &mut timing);
+        if let Some(ref capture) = self.timing {
+            *capture.lock().unwrap() = Some(timing);
+        }
+        stream
+    }
+}
+
+/// An HTTP client for URLs with IPv4 or IPv6 hosts, connecting only to
+/// addresses of the family if one is given, with the default timeouts.
+pub fn client(family: Option<Family>) -> Client {
+    client_from(family, Source::default(), Resolution::default())
+}
+
+/// Like `client`, but connecting from the source and resolving hosts as
+/// given.
+pub fn client_from(family: Option<Family>,
+                   source: Source,
+                   resolution: Resolution) -> Client {
+    Client::with_connector(Connector::new(family, source, resolution))
+}
@@ -0,0 +1,60 @@
+use std::ffi::CStr;
+
+use jsonway::{ObjectBuilder, ObjectSerializer};
+use libc;
+
+/// How a star process identifies itself to peers.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub id: String,
+    pub hostname: String,
+}
+
+impl Identity {
+    /// An identity with the supplied id, which defaults to the hostname.
+    pub fn new(id: Option<String>) -> Identity {
+        let hostname = hostname();
+        Identity {
+            id: id.unwrap_or(hostname.clone()),
+            hostname: hostname,
+        }
+    }
+}
+
+/// Response header in which star servers report the address that a request
+/// came from, as they observed it.
+pub const REMOTE_ADDRESS_HEADER: &'static str = "X-Star-Remote-Address";
+
+/// The answer to a `GET /whoami?nonce=...` request.
+pub struct Whoami {
+    pub identity: Identity,
+    pub nonce: Option<String>,
+    /// The source address of the request, as observed by the responder.
+    pub remote_address: String,
+}
+
+pub struct WhoamiSerializer;
+
+impl ObjectSerializer<Whoami> for WhoamiSerializer {
+    fn root(&self) -> Option<&str> { Some("whoami") }
+    fn build(&self, whoami: &Whoami, json: &mut ObjectBuilder) {
+        json.set("id", whoami.identity.id.clone());
+        json.set("hostname", whoami.identity.hostname.clone());
+        if let Some(ref nonce) = whoami.nonce {
+            json.set("nonce", nonce.clone());
+        }
+        json.set("remoteAddress", whoami.remote_address.clone());
+    }
+}
+
+fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    let result = unsafe {
+        libc::gethostname(buf.as_mut_ptr(), buf.len() as libc::size_t)
+    };
+    if result != 0 {
+        return "localhost".to_string();
+    }
+    buf[buf.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned()
+}
@@ -1,7 +1,22 @@
+pub mod dns;
+pub mod http;
+pub mod identity;
 pub mod logging;
+pub mod net;
+pub mod ports;
+pub mod sockopt;
+
+use std::process;
 
 pub const MS_PER_SEC: u32 = 1000;
 
+/// Prints the cause of a fatal error, e.g. invalid configuration, and exits
+/// with a failure status.
+pub fn exit_with(cause: String) -> ! {
+    eprintln!("{}", cause);
+    process::exit(1);
+}
+
 pub fn print_banner() {
     info!("
    _____ _____ ___  ______
@@ -0,0 +1,356 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream,
+    ToSocketAddrs, UdpSocket};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::time::Duration;
+
+use common::dns::{self, Resolver};
+use common::sockopt;
+
+use libc;
+use rustc_serialize::{Decodable, Decoder};
+
+/// An IP address family.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    pub fn of(ip: &IpAddr) -> Family {
+        match *ip {
+            IpAddr::V4(_) => Family::Ipv4,
+            IpAddr::V6(_) => Family::Ipv6,
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Family, String> {
+        match name {
+            "ipv4" => Ok(Family::Ipv4),
+            "ipv6" => Ok(Family::Ipv6),
+            _ => Err(format!("Unknown address family [{}]; expected ipv4 or \
+                              ipv6",
+                             name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Family::Ipv4 => "ipv4",
+            Family::Ipv6 => "ipv6",
+        }
+    }
+}
+
+// Decoded from its name, e.g. `"ipv6"`.
+impl Decodable for Family {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Family, D::Error> {
+        let name = try!(d.read_str());
+        Family::parse(&name).map_err(|cause| d.error(&cause))
+    }
+}
+
+/// Joins a host and port, bracketing IPv6 literals, e.g. `[::1]:9000`.
+pub fn join_host_port(host: &str, port: u16) -> String {
+    format!("{}:{}", bracket_host(host), port)
+}
+
+/// Brackets the host if it is an IPv6 literal, as required in URLs.
+pub fn bracket_host(host: &str) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+/// Splits the `host[:port]` authority of a URL into the host, without any
+/// brackets, and the port, if any.  IPv6 literals must be bracketed when
+/// followed by a port, e.g. `[fd00::1]:9000`.
+pub fn split_host_port(authority: &str)
+                       -> Result<(String, Option<u16>), String> {
+    let (host, port) =
+        if authority.starts_with('[') {
+            let end = match authority.find(']') {
+                Some(end) => end,
+                None => return Err(
+                    format!("Unterminated IPv6 address in [{}]", authority)),
+            };
+            let rest = &authority[end + 1..];
+            let port = if rest.is_empty() {
+                None
+            } else if rest.starts_with(':') {
+                Some(&rest[1..])
+            } else {
+                return Err(format!("Invalid authority [{}]", authority));
+            };
+            (&authority[1..end], port)
+        } else if authority.matches(':').count() > 1 {
+            // An unbracketed IPv6 literal cannot carry a port.
+            (authority, None)
+        } else {
+            match authority.rfind(':') {
+                Some(index) =>
+                    (&authority[..index], Some(&authority[index + 1..])),
+                None => (authority, None),
+            }
+        };
+    match port {
+        Some(port) => match port.parse::<u16>() {
+            Ok(port) => Ok((host.to_string(), Some(port))),
+            Err(_) => Err(format!("Invalid port in [{}]", authority)),
+        },
+        None => Ok((host.to_string(), None)),
+    }
+}
+
+/// Parses a comma-separated list of addresses to bind, e.g: `0.0.0.0,::`.
+/// IPv6 addresses may be bracketed.
+pub fn parse_addresses(addresses: &str) -> Vec<String> {
+    addresses.split(',')
+        .map(|address| address.trim())
+        .filter(|address| !address.is_empty())
+        .map(|address| unbracket_host(address).to_string())
+        .collect()
+}
+
+/// Strips the brackets of an IPv6 literal host, e.g. `[::1]` is `::1`.
+pub fn unbracket_host(host: &str) -> &str {
+    if host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else {
+        host
+    }
+}
+
+/// How the addresses of a host are found: by the system resolver, by
+/// querying a particular nameserver, or not at all when fixed addresses
+/// override resolution, like the `--resolve` option of curl.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Resolution {
+    /// The addresses of any host, if not empty.
+    pub addresses: Vec<IpAddr>,
+    pub nameserver: Option<SocketAddr>,
+}
+
+impl Resolution {
+    /// Resolves hosts with the nameserver, or the system resolver if none
+    /// is given.
+    pub fn with_nameserver(nameserver: Option<SocketAddr>) -> Resolution {
+        Resolution { addresses: vec!(), nameserver: nameserver, }
+    }
+}
+
+/// Resolves a host, which may be a bracketed IPv6 literal, and port to
+/// socket addresses, keeping only those of the family if one is given.
+pub fn resolve(host: &str,
+               port: u16,
+               family: Option<Family>,
+               resolution: &Resolution) -> io::Result<Vec<SocketAddr>> {
+    let host = unbracket_host(host);
+    let addrs: Vec<SocketAddr> =
+        if !resolution.addresses.is_empty() {
+            resolution.addresses.iter()
+                .map(|ip| SocketAddr::new(*ip, port))
+                .collect()
+        } else if let Some(nameserver) = resolution.nameserver {
+            try!(dns::resolve_dual_stack(&Resolver::new(nameserver), host)
+                    .map_err(|cause| io::Error::new(io::ErrorKind::Other,
+                                                    cause)))
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect()
+        } else {
+            try!((host, port).to_socket_addrs()).collect()
+        };
+    let addrs: Vec<SocketAddr> = addrs.into_iter()
+        .filter(|addr| family.map(|f| f == Family::of(&addr.ip()))
+                             .unwrap_or(true))
+        .collect();
+    if addrs.is_empty() {
+        let family_name = family.map(|f| format!("{} ", f.name()))
+            .unwrap_or(String::new());
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("No {}addresses for [{}]", family_name, host)));
+    }
+    Ok(addrs)
+}
+
+/// Where outgoing connections leave from, on multi-homed hosts: a local
+/// address and/or a network device, e.g:
+/// `{"address": "10.0.1.5", "device": "eth1"}`.
+#[derive(Clone, Debug, Default, PartialEq, RustcDecodable)]
+pub struct Source {
+    pub address: Option<String>,
+    /// Bound with `SO_BINDTODEVICE`, which usually requires `CAP_NET_RAW`.
+    pub device: Option<String>,
+}
+
+impl Source {
+    /// Whether the system may choose the source.
+    pub fn is_any(&self) -> bool {
+        self.address.is_none() && self.device.is_none()
+    }
+
+    /// The family of the source address, if one is set.
+    pub fn family(&self) -> Option<Family> {
+        self.address.as_ref()
+            .and_then(|address| unbracket_host(address).parse::<IpAddr>().ok())
+            .map(|ip| Family::of(&ip))
+    }
+
+    /// The local address to bind to connect to the peer: the source
+    /// address, or the unspecified address of the peer's family.
+    pub fn local_addr(&self, peer: &SocketAddr) -> io::Result<SocketAddr> {
+        let ip = match self.address {
+            Some(ref address) => match unbracket_host(address).parse() {
+                Ok(ip) if Family::of(&ip) == Family::of(&peer.ip()) => ip,
+                Ok(_) => return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Source address [{}] cannot reach [{}]",
+                            address, peer))),
+                Err(_) => return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid source address [{}]", address))),
+            },
+            None => match *peer {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+                SocketAddr::V6(_) =>
+                    IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+            },
+        };
+        Ok(SocketAddr::new(ip, 0))
+    }
+
+    /// Binds an unconnected socket to the source device and address, to
+    /// connect to the peer.
+    pub fn bind<S: AsRawFd>(&self, socket: &S, peer: &SocketAddr)
+                            -> io::Result<()> {
+        if let Some(ref device) = self.device {
+            try!(bind_device(socket, device));
+        }
+        if self.address.is_some() {
+            let local_addr = try!(self.local_addr(peer));
+            try!(sockopt::bind(socket.as_raw_fd(), &local_addr));
+        }
+        Ok(())
+    }
+
+    /// The source actually used by a socket with the local address, for
+    /// reporting.
+    pub fn used(&self, local_ip: Option<IpAddr>) -> Option<Source> {
+        let source = Source {
+            address: local_ip.map(|ip| format!("{}", ip))
+                .or(self.address.clone()),
+            device: self.device.clone(),
+        };
+        if source.is_any() { None } else { Some(source) }
+    }
+}
+
+/// Connects to the first of the addresses that accepts a connection from
+/// the source, waiting up to the timeout for each if one is given.
+pub fn connect(addrs: &[SocketAddr],
+               source: &Source,
+               timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::Other, "No addresses");
+    for addr in addrs {
+        match connect_from(addr, source, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(cause) => last_error = cause,
+        }
+    }
+    Err(last_error)
+}
+
+/// Connects to the address from the source.
+pub fn connect_from(addr: &SocketAddr,
+                    source: &Source,
+                    timeout: Option<Duration>) -> io::Result<TcpStream> {
+    if source.is_any() {
+        return match timeout {
+            Some(timeout) => TcpStream::connect_timeout(addr, timeout),
+            None => TcpStream::connect(addr),
+        };
+    }
+
+    let domain = match *addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let fd = unsafe {
+        libc::socket(domain, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0)
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The stream owns the socket from here on, closing it on failure.
+    let stream = unsafe { TcpStream::from_raw_fd(fd) };
+    try!(source.bind(&stream, addr));
+
+    try!(stream.set_nonblocking(true));
+    match sockopt::connect(fd, addr) {
+        Ok(()) => (),
+        Err(ref cause) if cause.raw_os_error() == Some(libc::EINPROGRESS) => {
+            let wait_ms = timeout
+                .map(|t| (t.as_secs() * 1000) as libc::c_int +
+                         (t.subsec_nanos() / 1000000) as libc::c_int)
+                .unwrap_or(-1);
+            let mut pollfd =
+                libc::pollfd { fd: fd, events: libc::POLLOUT, revents: 0 };
+            let ready = unsafe { libc::poll(&mut pollfd, 1, wait_ms) };
+            if ready < 0 {
+                return Err(io::Error::last_os_error());
+            } else if ready == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("Timed out connecting to [{}]", addr)));
+            }
+            let error = try!(sockopt::get_int_option(fd,
+                                                     libc::SOL_SOCKET,
+                                                     libc::SO_ERROR));
+            if error != 0 {
+                return Err(io::Error::from_raw_os_error(error));
+            }
+        },
+        Err(cause) => return Err(cause),
+    }
+    try!(stream.set_nonblocking(false));
+    Ok(stream)
+}
+
+/// A UDP socket bound to the source, to send datagrams to the peer.
+pub fn udp_socket(peer: &SocketAddr, source: &Source)
+                  -> io::Result<UdpSocket> {
+    let socket = try!(UdpSocket::bind(try!(source.local_addr(peer))));
+    if let Some(ref device) = source.device {
+        try!(bind_device(&socket, device));
+    }
+    Ok(socket)
+}
+
+fn bind_device<S: AsRawFd>(socket: &S, device: &str) -> io::Result<()> {
+    sockopt::bind_to_device(socket.as_raw_fd(), device)
+        .map_err(|cause| io::Error::new(
+            cause.kind(),
+            format!("Failed to bind to device [{}]: {}", device, cause)))
+}
+
+/// Unwraps IPv4-mapped IPv6 addresses, such as the peer addresses of IPv4
+/// clients of a server bound to `::`, e.g. `::ffff:10.0.0.1` is
+/// `10.0.0.1`.
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    if let IpAddr::V6(v6) = ip {
+        let s = v6.segments();
+        if s[0] == 0 && s[1] == 0 && s[2] == 0 && s[3] == 0 && s[4] == 0 &&
+           s[5] == 0xffff {
+            return IpAddr::V4(Ipv4Addr::new((s[6] >> 8) as u8,
+                                            s[6] as u8,
+                                            (s[7] >> 8) as u8,
+                                            s[7] as u8));
+        }
+    }
+    ip
+}
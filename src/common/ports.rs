@@ -0,0 +1,30 @@
+/// Parses a comma-delimited list of ports and inclusive port ranges, such
+/// as `80,443,8000-8010`, into the ports it denotes in ascending order.
+pub fn parse_ports(spec: &str) -> Result<Vec<u16>, String> {
+    let mut ports = vec!();
+    for item in spec.split(",").map(|s| s.trim()).filter(|s| *s != "") {
+        let (first, last) = match item.find('-') {
+            Some(index) => (try!(parse_port(&item[..index])),
+                            try!(parse_port(&item[index + 1..]))),
+            None => {
+                let port = try!(parse_port(item));
+                (port, port)
+            },
+        };
+        if first > last {
+            return Err(format!("Invalid port range [{}]", item));
+        }
+        ports.extend(first..last);
+        ports.push(last);
+    }
+    ports.sort();
+    ports.dedup();
+    Ok(ports)
+}
+
+fn parse_port(text: &str) -> Result<u16, String> {
+    match text.trim().parse::<u16>() {
+        Ok(port) if port != 0 => Ok(port),
+        _ => Err(format!("Invalid port [{}]", text)),
+    }
+}
@@ -0,0 +1,109 @@
+use std::io;
+use std::mem;
+use std::net::SocketAddr;
+use std::os::unix::io::RawFd;
+
+use libc;
+
+/// Sets an integer socket option, e.g. `IP_TTL`.
+pub fn set_int_option(fd: RawFd,
+                      level: libc::c_int,
+                      name: libc::c_int,
+                      value: libc::c_int) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(fd,
+                         level,
+                         name,
+                         &value as *const libc::c_int as *const libc::c_void,
+                         mem::size_of::<libc::c_int>() as libc::socklen_t)
+    };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Gets an integer socket option, e.g. `IP_MTU`.
+pub fn get_int_option(fd: RawFd,
+                      level: libc::c_int,
+                      name: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(fd,
+                         level,
+                         name,
+                         &mut value as *mut libc::c_int as *mut libc::c_void,
+                         &mut len)
+    };
+    if result == 0 { Ok(value) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Binds the socket to a network device, e.g. `eth1`, so that its traffic
+/// leaves through that device whatever the routing table says.  This
+/// usually requires `CAP_NET_RAW`.
+pub fn bind_to_device(fd: RawFd, device: &str) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(fd,
+                         libc::SOL_SOCKET,
+                         libc::SO_BINDTODEVICE,
+                         device.as_ptr() as *const libc::c_void,
+                         device.len() as libc::socklen_t)
+    };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Binds the socket to a local address.
+pub fn bind(fd: RawFd, addr: &SocketAddr) -> io::Result<()> {
+    let (storage, len) = sockaddr_of(addr);
+    let result = unsafe {
+        libc::bind(fd,
+                   &storage as *const libc::sockaddr_storage
+                       as *const libc::sockaddr,
+                   len)
+    };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Starts connecting the socket to a remote address.  Non-blocking sockets
+/// report `EINPROGRESS` until the connection is established.
+pub fn connect(fd: RawFd, addr: &SocketAddr) -> io::Result<()> {
+    let (storage, len) = sockaddr_of(addr);
+    let result = unsafe {
+        libc::connect(fd,
+                      &storage as *const libc::sockaddr_storage
+                          as *const libc::sockaddr,
+                      len)
+    };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Converts a socket address into its C representation.
+fn sockaddr_of(addr: &SocketAddr)
+               -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match *addr {
+        SocketAddr::V4(ref v4) => {
+            let sin = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage
+                       as *mut libc::sockaddr_in)
+            };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = v4.port().to_be();
+            sin.sin_addr = libc::in_addr {
+                s_addr: u32::from(*v4.ip()).to_be(),
+            };
+            mem::size_of::<libc::sockaddr_in>()
+        },
+        SocketAddr::V6(ref v6) => {
+            let sin6 = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage
+                       as *mut libc::sockaddr_in6)
+            };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = v6.port().to_be();
+            sin6.sin6_flowinfo = v6.flowinfo();
+            sin6.sin6_addr = libc::in6_addr { s6_addr: v6.ip().octets() };
+            sin6.sin6_scope_id = v6.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        },
+    };
+    (storage, len as libc::socklen_t)
+}
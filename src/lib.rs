@@ -1,12 +1,18 @@
 extern crate hyper;
 extern crate jsonway;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate mio;
+extern crate regex;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
 extern crate rustc_serialize;
 extern crate threadpool;
 extern crate time;
+extern crate url;
 
 pub mod collect;
 pub mod common;
 pub mod probe;
+pub mod target;
@@ -0,0 +1,372 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use probe::protocol::{Failure, DEFAULT_TIMEOUT_MS};
+use probe::protocol::tcp;
+use probe::status::StatusCache;
+use probe::target::{HeartbeatOptions, TargetSpec};
+
+use time;
+
+/// The default time between heartbeats.
+pub const DEFAULT_HEARTBEAT_MS: u64 = 1000;
+
+const DEFAULT_PAYLOAD: &'static str = "star\n";
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowState {
+    /// No flow is established yet, e.g. because the target refuses new
+    /// connections.
+    Connecting,
+    Established,
+    /// The established flow broke; a new one is attempted after the
+    /// heartbeat interval.
+    Broken,
+}
+
+impl FlowState {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FlowState::Connecting => "connecting",
+            FlowState::Established => "established",
+            FlowState::Broken => "broken",
+        }
+    }
+}
+
+/// How an established flow broke.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Break {
+    /// The connection was reset, e.g. by a firewall that lost its state.
+    Reset(String),
+    /// The target closed the connection.
+    Closed(String),
+    /// A heartbeat went unanswered, e.g. because its packets were dropped.
+    Timeout(String),
+    /// A heartbeat was answered, but not as expected.
+    Mismatch(String),
+}
+
+impl Break {
+    /// A short, stable name for the kind of break.
+    pub fn reason(&self) -> &'static str {
+        match *self {
+            Break::Reset(_) => "reset",
+            Break::Closed(_) => "closed",
+            Break::Timeout(_) => "timeout",
+            Break::Mismatch(_) => "mismatch",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match *self {
+            Break::Reset(ref message) |
+            Break::Closed(ref message) |
+            Break::Timeout(ref message) |
+            Break::Mismatch(ref message) => message,
+        }
+    }
+}
+
+/// The state of the long-lived connection to a target.  Times are in
+/// seconds since the epoch.
+#[derive(Clone, Debug)]
+pub struct Flow {
+    pub state: FlowState,
+    /// The local address of the current or most recent flow.
+    pub local_address: Option<String>,
+    pub established_at: Option<i64>,
+    /// Heartbeats answered over the current or most recent flow.
+    pub heartbeats: u64,
+    pub last_heartbeat_at: Option<i64>,
+    pub broken_at: Option<i64>,
+    /// How the most recent flow broke.
+    pub break_cause: Option<Break>,
+    /// Number of established flows that broke so far.
+    pub breaks: u64,
+}
+
+impl Flow {
+    pub fn new() -> Flow {
+        Flow {
+            state: FlowState::Connecting,
+            local_address: None,
+            established_at: None,
+            heartbeats: 0,
+            last_heartbeat_at: None,
+            broken_at: None,
+            break_cause: None,
+            breaks: 0,
+        }
+    }
+}
+
+/// Starts a thread that keeps a long-lived connection to the target and
+/// sends heartbeats over it, recording the state of the flow in the status
+/// cache.  The thread exits once `stop` is set.
+pub fn start_heartbeat(target: TargetSpec,
+                       status_cache: Arc<RwLock<StatusCache>>,
+                       stop: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        info!("Starting heartbeats to target [{}]", target.url);
+        run(&target, &status_cache, &stop);
+        info!("Stopped heartbeats to target [{}]", target.url);
+    });
+}
+
+fn run(target: &TargetSpec,
+       status_cache: &Arc<RwLock<StatusCache>>,
+       stop: &Arc<AtomicBool>) {
+    let default_options = HeartbeatOptions {
+        interval_ms: None,
+        send: None,
+        expect: None,
+    };
+    let options = target.heartbeat.as_ref().unwrap_or(&default_options);
+    let interval = Duration::from_millis(
+        options.interval_ms.unwrap_or(DEFAULT_HEARTBEAT_MS));
+    let timeout_ms = target.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+
+    let mut flow = Flow::new();
+    let record = |flow: &Flow| status_cache.write().unwrap()
+        .record_heartbeat(&target.url, flow);
+
+    while !stop.load(Ordering::SeqCst) {
+        let mut stream = match tcp::connect(target, timeout_ms) {
+            Ok(stream) => stream,
+            Err(failure) => {
+                if let Failure::Invalid(ref message) = failure {
+                    warn!("Cannot send heartbeats to target [{}]: {}",
+                          target.url, message);
+                    return;
+                }
+                thread::sleep(interval);
+                continue;
+            },
+        };
+
+        flow.state = FlowState::Established;
+        flow.local_address = stream.local_addr().ok()
+            .map(|addr| format!("{}", addr));
+        flow.established_at = Some(now());
+        flow.heartbeats = 0;
+        record(&flow);
+        info!("Established flow [{}] to target [{}]",
+              flow.local_address.clone().unwrap_or(String::new()),
+              target.url);
+
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+            match heartbeat(target, options, &mut stream, timeout_ms) {
+                Ok(()) => {
+                    flow.heartbeats += 1;
+                    flow.last_heartbeat_at = Some(now());
+                    record(&flow);
+                },
+                Err(cause) => {
+                    warn!("Flow to target [{}] broke after {} heartbeats \
+                           ({}: {})",
+                          target.url,
+                          flow.heartbeats,
+                          cause.reason(),
+                          cause.message());
+                    flow.state = FlowState::Broken;
+                    flow.broken_at = Some(now());
+                    flow.break_cause = Some(cause);
+                    flow.breaks += 1;
+                    record(&flow);
+                    break;
+                },
+            }
+            thread::sleep(interval);
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// Sends a heartbeat over the stream and waits for its reply.
+fn heartbeat(target: &TargetSpec,
+             options: &HeartbeatOptions,
+             stream: &mut TcpStream,
+             timeout_ms: u64) -> Result<(), Break> {
+    let _ = stream.set_read_timeout(Some(Duration::from_millis(timeout_ms)));
+
+    match target.scheme() {
+        Some("http") => http_heartbeat(target, stream),
+        _ => {
+            let payload = options.send.as_ref()
+                .map(|s| &s[..])
+                .unwrap_or(DEFAULT_PAYLOAD);
+            try!(stream.write_all(payload.as_bytes()).map_err(broken));
+            match options.expect {
+                Some(ref expect) =>
+                    tcp::read_expected(stream, expect, timeout_ms)
+                        .map_err(|failure| Break::Mismatch(
+                            failure.message().to_string())),
+                None => {
+                    let mut buf = [0u8; 4096];
+                    match stream.read(&mut buf) {
+                        Ok(0) => Err(Break::Closed(
+                            "The target closed the connection".to_string())),
+                        Ok(_) => Ok(()),
+                        Err(cause) => Err(broken(cause)),
+                    }
+                },
+            }
+        },
+    }
+}
+
+/// Sends a keep-alive `GET` request for the target's path and reads the
+/// whole response, which must leave the connection open.
+fn http_heartbeat(target: &TargetSpec, stream: &mut TcpStream)
+                  -> Result<(), Break> {
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\
+                           Connection: keep-alive\r\n\r\n",
+                          target.path(),
+                          target.authority().unwrap_or(""));
+    try!(stream.write_all(request.as_bytes()).map_err(broken));
+
+    let received = try!(read_until(stream, vec!(), |r|
+        Ok(find(r, b"\r\n\r\n").is_some())));
+    let header_end = find(&received, b"\r\n\r\n").unwrap() + 4;
+
+    let head = String::from_utf8_lossy(&received[..header_end]).to_lowercase();
+    let header = |name: &str| head.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(n), Some(value)) if n.trim() == name =>
+                    Some(value.trim().to_string()),
+                _ => None,
+            }
+        })
+        .next();
+
+    // Read the body, so that the next response starts at a known offset.
+    let body = received[header_end..].to_vec();
+    if let Some(length) = header("content-length")
+        .and_then(|l| l.parse::<usize>().ok()) {
+        try!(read_until(stream, body, |b| Ok(b.len() >= length)));
+    } else if header("transfer-encoding").map(|t| t.contains("chunked"))
+        .unwrap_or(false) {
+        try!(read_until(stream, body, chunked_complete));
+    } else {
+        return Err(Break::Closed(
+            "The response did not allow the connection to be kept alive"
+                .to_string()));
+    }
+
+    if header("connection").map(|c| c.contains("close")).unwrap_or(false) {
+        return Err(Break::Closed(
+            "The target closed the keep-alive connection".to_string()));
+    }
+    Ok(())
+}
+
+/// Reads from the stream, appending to `received`, until `done` holds.
+fn read_until<F>(stream: &mut TcpStream, mut received: Vec<u8>, done: F)
+                 -> Result<Vec<u8>, Break>
+    where F: Fn(&[u8]) -> Result<bool, Break> {
+    let mut buf = [0u8; 4096];
+    while !try!(done(&received)) {
+        if received.len() >= MAX_RESPONSE_BYTES {
+            return Err(Break::Mismatch(
+                "The response is too long".to_string()));
+        }
+        match stream.read(&mut buf) {
+            Ok(0) => return Err(Break::Closed(
+                "The target closed the connection".to_string())),
+            Ok(n) => received.extend(buf[..n].iter().cloned()),
+            Err(cause) => return Err(broken(cause)),
+        }
+    }
+    Ok(received)
+}
+
+/// Whether a chunked body has been received in full, up to the last chunk
+/// and the empty line after any trailers.
+fn chunked_complete(body: &[u8]) -> Result<bool, Break> {
+    let mut offset = 0;
+    loop {
+        let line_end = match find(&body[offset..], b"\r\n") {
+            Some(index) => offset + index,
+            None => return Ok(false),
+        };
+        let line = String::from_utf8_lossy(&body[offset..line_end])
+            .into_owned();
+        // Chunk extensions follow the size after a semicolon.
+        let size = line.split(';').next().unwrap_or("").trim().to_string();
+        let size = match usize::from_str_radix(&size, 16) {
+            Ok(size) if size <= MAX_RESPONSE_BYTES => size,
+            Ok(_) => return Err(Break::Mismatch(
+                "The response is too long".to_string())),
+            Err(_) => return Err(Break::Mismatch(
+                format!("Invalid chunk size [{}]", size))),
+        };
+        offset = line_end + 2;
+
+        if size == 0 {
+            let trailers = &body[offset..];
+            return Ok(trailers.starts_with(b"\r\n") ||
+                      find(trailers, b"\r\n\r\n").is_some());
+        }
+        if offset + size + 2 > body.len() {
+            return Ok(false);
+        }
+        offset += size + 2;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Classifies an error on an established connection.
+fn broken(cause: io::Error) -> Break {
+    match cause.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut =>
+            Break::Timeout("A heartbeat went unanswered".to_string()),
+        _ => Break::Reset(format!("{}", cause)),
+    }
+}
+
+fn now() -> i64 {
+    time::get_time().sec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::chunked_complete;
+
+    #[test]
+    fn complete_chunked_bodies() {
+        assert_eq!(chunked_complete(b"0\r\n\r\n").ok(), Some(true));
+        assert_eq!(chunked_complete(b"5\r\nhello\r\n0\r\n\r\n").ok(),
+                   Some(true));
+        let trailers = b"5;ext=1\r\nhello\r\n0\r\nX-A: b\r\n\r\n";
+        assert_eq!(chunked_complete(trailers).ok(), Some(true));
+    }
+
+    #[test]
+    fn incomplete_chunked_bodies() {
+        assert_eq!(chunked_complete(b"").ok(), Some(false));
+        assert_eq!(chunked_complete(b"5\r\nhel").ok(), Some(false));
+        assert_eq!(chunked_complete(b"5\r\nhello\r\n0\r\n").ok(),
+                   Some(false));
+        // A chunk ending with the bytes of the last chunk is not the end.
+        assert_eq!(chunked_complete(b"7\r\n0\r\n\r\n\r\n").ok(), Some(false));
+    }
+
+    #[test]
+    fn invalid_chunk_sizes() {
+        assert!(chunked_complete(b"zz\r\nhello\r\n").is_err());
+    }
+}
@@ -1,4 +1,6 @@
-use probe::status::{Status, Target};
+use probe::status::{RangeSummary, Status, Target};
+use probe::throughput::Throughput;
+use probe::trace::{Hop, Trace};
 
 use jsonway::{ObjectBuilder, ObjectSerializer};
 
@@ -12,6 +14,26 @@ impl ObjectSerializer<Status> for StatusSerializer {
             TargetSerializer.serialize(s, false)).collect::<Vec<_>>();
 
         json.set("targets", target_json);
+
+        if !status.ranges.is_empty() {
+            let range_json = status.ranges.iter().map(|r|
+                RangeSummarySerializer.serialize(r, false)).collect::<Vec<_>>();
+
+            json.set("ranges", range_json);
+        }
+    }
+}
+
+pub struct RangeSummarySerializer;
+
+impl ObjectSerializer<RangeSummary> for RangeSummarySerializer {
+    fn root(&self) -> Option<&str> { Some("range") }
+    fn build(&self, range: &RangeSummary, json: &mut ObjectBuilder) {
+        json.set("url", range.url.clone());
+        json.set("targets", range.target_urls.len());
+        json.set("reachable", range.reachable);
+        json.set("degraded", range.degraded);
+        json.set("unreachable", range.unreachable);
     }
 }
 
@@ -22,5 +44,164 @@ impl ObjectSerializer<Target> for TargetSerializer {
     fn build(&self, target: &Target, json: &mut ObjectBuilder) {
         json.set("url", target.url.clone());
         json.set("reachable", target.reachable.clone());
+        json.set("reachability", target.reachability.name().to_string());
+        json.set("lossPct", target.loss_pct);
+        if let Some(rtt_ms) = target.rtt_ms {
+            json.set("rttMs", rtt_ms);
+        }
+        if let Some(ref observed_address) = target.observed_address {
+            json.set("observedSourceAddress", observed_address.clone());
+        }
+        if let Some(resolved_address) = target.resolved_address {
+            json.set("resolvedAddress", format!("{}", resolved_address));
+        }
+        if let Some(ref source) = target.source {
+            json.object("source", |json| {
+                if let Some(ref address) = source.address {
+                    json.set("address", address.clone());
+                }
+                if let Some(ref device) = source.device {
+                    json.set("device", device.clone());
+                }
+            });
+        }
+        if let Some(path_mtu) = target.path_mtu {
+            json.set("pathMtu", path_mtu);
+        }
+        if let Some(ref responder) = target.responder {
+            json.object("responder", |json| {
+                json.set("id", responder.id.clone());
+                json.set("hostname", responder.hostname.clone());
+            });
+        }
+        if let Some(ref timing) = target.timing {
+            json.object("timing", |json| {
+                if let Some(dns_ms) = timing.dns_ms {
+                    json.set("dnsMs", dns_ms);
+                }
+                if let Some(connect_ms) = timing.connect_ms {
+                    json.set("connectMs", connect_ms);
+                }
+                if let Some(tls_ms) = timing.tls_ms {
+                    json.set("tlsMs", tls_ms);
+                }
+                if let Some(ttfb_ms) = timing.ttfb_ms {
+                    json.set("ttfbMs", ttfb_ms);
+                }
+                json.set("totalMs", timing.total_ms);
+            });
+        }
+        if let Some(ref flow) = target.heartbeat {
+            json.object("heartbeat", |json| {
+                json.set("state", flow.state.name().to_string());
+                if let Some(ref local_address) = flow.local_address {
+                    json.set("localAddress", local_address.clone());
+                }
+                if let Some(established_at) = flow.established_at {
+                    json.set("establishedAt", established_at);
+                }
+                json.set("heartbeats", flow.heartbeats);
+                if let Some(last_heartbeat_at) = flow.last_heartbeat_at {
+                    json.set("lastHeartbeatAt", last_heartbeat_at);
+                }
+                json.set("breaks", flow.breaks);
+                if let Some(broken_at) = flow.broken_at {
+                    json.set("brokenAt", broken_at);
+                }
+                if let Some(ref cause) = flow.break_cause {
+                    json.object("break", |json| {
+                        json.set("reason", cause.reason().to_string());
+                        json.set("message", cause.message().to_string());
+                    });
+                }
+            });
+        }
+        if !target.families.is_empty() {
+            json.object("families", |json| {
+                for family in target.families.iter() {
+                    json.object(family.family.name(), |json| {
+                        json.set("reachability",
+                                 family.reachability.name().to_string());
+                        json.set("lossPct", family.loss_pct);
+                        if let Some(rtt_ms) = family.rtt_ms {
+                            json.set("rttMs", rtt_ms);
+                        }
+                        if let Some(ref failure) = family.failure {
+                            json.object("failure", |json| {
+                                json.set("reason",
+                                         failure.reason().to_string());
+                                json.set("message",
+                                         failure.message().to_string());
+                            });
+                        }
+                    });
+                }
+            });
+        }
+        if !target.throughput.is_empty() {
+            json.object("throughput", |json| {
+                for result in target.throughput.iter() {
+                    json.set(result.direction.name(),
+                             ThroughputSerializer.serialize(result, false));
+                }
+            });
+        }
+        if let Some(ref failure) = target.failure {
+            json.object("failure", |json| {
+                json.set("reason", failure.reason().to_string());
+                json.set("message", failure.message().to_string());
+            });
+        }
+    }
+}
+
+pub struct TraceSerializer;
+
+impl ObjectSerializer<Trace> for TraceSerializer {
+    fn root(&self) -> Option<&str> { Some("trace") }
+    fn build(&self, trace: &Trace, json: &mut ObjectBuilder) {
+        json.set("url", trace.url.clone());
+        json.set("destination", trace.destination.clone());
+        json.set("reached", trace.reached);
+
+        let hops_json = trace.hops.iter().map(|h|
+            HopSerializer.serialize(h, false)).collect::<Vec<_>>();
+
+        json.set("hops", hops_json);
+    }
+}
+
+pub struct HopSerializer;
+
+impl ObjectSerializer<Hop> for HopSerializer {
+    fn root(&self) -> Option<&str> { Some("hop") }
+    fn build(&self, hop: &Hop, json: &mut ObjectBuilder) {
+        json.set("ttl", hop.ttl);
+        if let Some(ref address) = hop.address {
+            json.set("address", address.clone());
+        }
+        if let Some(rtt_ms) = hop.rtt_ms {
+            json.set("rttMs", rtt_ms);
+        }
+        json.set("destination", hop.destination);
+        if let Some(icmp_type) = hop.icmp_type {
+            json.set("icmpType", icmp_type);
+        }
+        if let Some(icmp_code) = hop.icmp_code {
+            json.set("icmpCode", icmp_code);
+        }
+    }
+}
+
+pub struct ThroughputSerializer;
+
+impl ObjectSerializer<Throughput> for ThroughputSerializer {
+    fn root(&self) -> Option<&str> { Some("throughput") }
+    fn build(&self, result: &Throughput, json: &mut ObjectBuilder) {
+        json.set("direction", result.direction.name().to_string());
+        json.set("bytes", result.bytes);
+        json.set("durationMs", result.duration_ms);
+        json.set("mbps", result.mbps);
+        json.set("measuredAt", result.measured_at);
     }
 }
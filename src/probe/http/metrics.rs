@@ -0,0 +1,127 @@
+use probe::status::{Reachability, Status};
+
+/// Renders the status in the Prometheus text exposition format, with one
+/// sample per target and metric.
+pub fn render(status: &Status) -> String {
+    let mut targets = status.targets.clone();
+    targets.sort_by(|a, b| a.url.cmp(&b.url));
+
+    let mut out = String::new();
+
+    out.push_str("# HELP star_probe_reachability Reachability of the target \
+                  in the most recent round: 1 reachable, 0.5 degraded, \
+                  0 unreachable.\n");
+    out.push_str("# TYPE star_probe_reachability gauge\n");
+    for target in targets.iter() {
+        sample(&mut out, "star_probe_reachability", &target.url, None,
+               reachability_value(target.reachability));
+    }
+
+    out.push_str("# HELP star_probe_family_reachability Reachability of the \
+                  addresses of each family of a hostname target in the most \
+                  recent round.\n");
+    out.push_str("# TYPE star_probe_family_reachability gauge\n");
+    for target in targets.iter() {
+        for family in target.families.iter() {
+            sample(&mut out, "star_probe_family_reachability", &target.url,
+                   Some(("family", family.family.name())),
+                   reachability_value(family.reachability));
+        }
+    }
+
+    out.push_str("# HELP star_probe_loss_percent Percentage of the attempts \
+                  of the most recent round that failed.\n");
+    out.push_str("# TYPE star_probe_loss_percent gauge\n");
+    for target in targets.iter() {
+        sample(&mut out, "star_probe_loss_percent", &target.url, None,
+               target.loss_pct);
+    }
+
+    out.push_str("# HELP star_probe_rtt_milliseconds Mean round trip time of \
+                  the successful attempts of the most recent round.\n");
+    out.push_str("# TYPE star_probe_rtt_milliseconds gauge\n");
+    for target in targets.iter() {
+        if let Some(rtt_ms) = target.rtt_ms {
+            sample(&mut out, "star_probe_rtt_milliseconds", &target.url, None,
+                   rtt_ms);
+        }
+    }
+
+    out.push_str("# HELP star_probe_phase_milliseconds Time spent in each \
+                  phase of the most recent timed probe.\n");
+    out.push_str("# TYPE star_probe_phase_milliseconds gauge\n");
+    for target in targets.iter() {
+        let timing = match target.timing {
+            Some(ref timing) => timing,
+            None => continue,
+        };
+        let phases = vec!(
+            ("dns", timing.dns_ms),
+            ("connect", timing.connect_ms),
+            ("tls", timing.tls_ms),
+            ("ttfb", timing.ttfb_ms),
+            ("total", Some(timing.total_ms)));
+        for (phase, value) in phases {
+            if let Some(value) = value {
+                sample(&mut out, "star_probe_phase_milliseconds", &target.url,
+                       Some(("phase", phase)), value);
+            }
+        }
+    }
+
+    out.push_str("# HELP star_probe_path_mtu_bytes Largest IP packet that \
+                  reached the target and back.\n");
+    out.push_str("# TYPE star_probe_path_mtu_bytes gauge\n");
+    for target in targets.iter() {
+        if let Some(path_mtu) = target.path_mtu {
+            sample(&mut out, "star_probe_path_mtu_bytes", &target.url, None,
+                   path_mtu as f64);
+        }
+    }
+
+    out.push_str("# HELP star_probe_flow_breaks_total Established flows to \
+                  the target that broke.\n");
+    out.push_str("# TYPE star_probe_flow_breaks_total counter\n");
+    for target in targets.iter() {
+        if let Some(ref flow) = target.heartbeat {
+            sample(&mut out, "star_probe_flow_breaks_total", &target.url, None,
+                   flow.breaks as f64);
+        }
+    }
+
+    out.push_str("# HELP star_probe_throughput_mbps Throughput of the most \
+                  recent test to the target in each direction.\n");
+    out.push_str("# TYPE star_probe_throughput_mbps gauge\n");
+    for target in targets.iter() {
+        for result in target.throughput.iter() {
+            sample(&mut out, "star_probe_throughput_mbps", &target.url,
+                   Some(("direction", result.direction.name())), result.mbps);
+        }
+    }
+
+    out
+}
+
+fn reachability_value(reachability: Reachability) -> f64 {
+    match reachability {
+        Reachability::Reachable => 1.0,
+        Reachability::Degraded => 0.5,
+        Reachability::Unreachable => 0.0,
+    }
+}
+
+fn sample(out: &mut String,
+          name: &str,
+          url: &str,
+          label: Option<(&str, &str)>,
+          value: f64) {
+    out.push_str(&format!("{}{{url=\"{}\"", name, escape(url)));
+    if let Some((label, label_value)) = label {
+        out.push_str(&format!(",{}=\"{}\"", label, escape(label_value)));
+    }
+    out.push_str(&format!("}} {}\n", value));
+}
+
+fn escape(value: &str) -> String {
+    value.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
+}
@@ -1,47 +1,104 @@
+use std::cmp;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 
+use common::http::{self, decode_segment, query_param, split_query, Query};
+use common::identity::{Identity, Whoami, WhoamiSerializer,
+    REMOTE_ADDRESS_HEADER};
+use common::net::{canonical_ip, Source};
 use probe::status::StatusCache;
-use probe::http::json::StatusSerializer;
+use probe::http::json::{StatusSerializer, ThroughputSerializer,
+    TraceSerializer};
+use probe::http::metrics;
+use probe::protocol::elapsed_ms;
+use probe::target::TargetSpec;
+use probe::throughput::{self, Direction, TestLimiter, Throughput};
+use probe::trace;
 
 use hyper;
-use hyper::header::ContentType;
-use hyper::Server;
+use hyper::header::{Connection, ContentType};
 use hyper::server::Request;
 use hyper::server::Response;
+use hyper::status::StatusCode;
 use hyper::net::Fresh;
 use hyper::uri::RequestUri::AbsolutePath;
 use jsonway::{ObjectSerializer};
+use time;
 
 pub fn start_server(status_cache: Arc<RwLock<StatusCache>>,
-                    address: String,
+                    identity: Identity,
+                    source: Source,
+                    nameserver: Option<SocketAddr>,
+                    addresses: Vec<String>,
                     port: u16) {
-    let bind_addr: &str = &format!("{}:{}", address, port);
-    let status_handler = StatusHandler { status_cache: status_cache, };
-    let serve = move |req: Request, res: Response<Fresh>| {
-        status_handler.handle(req, res);
+    let status_handler = StatusHandler {
+        status_cache: status_cache,
+        identity: identity,
+        source: source,
+        nameserver: nameserver,
+        throughput_tests:
+            TestLimiter::new(throughput::MAX_CONCURRENT_TESTS),
+        traces: TestLimiter::new(trace::MAX_CONCURRENT_TRACES),
     };
-    info!("Starting HTTP server on [{}]", bind_addr);
-    Server::http(bind_addr).map(|s| s.handle(serve).unwrap()).unwrap();
+    http::serve(&addresses, port, move |req, res| {
+        status_handler.handle(req, res);
+    });
 }
 
+/// Targets are addressed as `/targets/{id}`, where the id is the
+/// percent-encoded target URL.
+const TARGETS_PREFIX: &'static str = "/targets/";
+const TRACE_SUFFIX: &'static str = "/trace";
+const THROUGHPUT_SUFFIX: &'static str = "/throughput";
+
 struct StatusHandler {
     status_cache: Arc<RwLock<StatusCache>>,
+    identity: Identity,
+    /// The source that on-demand traces and throughput tests leave from.
+    source: Source,
+    /// The nameserver that on-demand traces and throughput tests resolve
+    /// hosts with, if not the system resolver.
+    nameserver: Option<SocketAddr>,
+    /// Each throughput test holds a server thread until it is done, so only
+    /// a few may run at once.
+    throughput_tests: TestLimiter,
+    /// Likewise each trace, which may wait a second for every hop.
+    traces: TestLimiter,
 }
 
 impl StatusHandler {
-    fn handle(&self, req: Request, mut res: Response<Fresh>) {
+    fn handle(&self, mut req: Request, mut res: Response<Fresh>) {
         info!("Request from [{:?}]: {:?} {:?}",
               req.remote_addr,
               req.method,
               req.uri);
 
-        match req.uri {
-            AbsolutePath(ref path) =>
-                match (&req.method, &path[..]) {
+        // Report the source address as observed here, so that probes can
+        // tell when it was rewritten along the way.
+        let remote_address =
+            format!("{}", canonical_ip(req.remote_addr.ip()));
+        res.headers_mut().set_raw(REMOTE_ADDRESS_HEADER,
+                                  vec!(remote_address.clone().into_bytes()));
+
+        let uri = req.uri.clone(); // prevent simultaneous mutable borrow
+
+        match uri {
+            AbsolutePath(ref path) => {
+                let (path, query) = split_query(path);
+                match (&req.method, path) {
                     (&hyper::Get, "/status") => {
                         // Get the current status from the cache.
-                        let status = &self.status_cache.read().unwrap().poll();
+                        let mut status =
+                            self.status_cache.read().unwrap().poll();
+
+                        // Leave out the targets expanded from range URLs
+                        // when only their summaries are wanted.
+                        if query_param(&query, "summary") == Some("true") {
+                            let ranges = status.ranges.clone();
+                            status.targets.retain(|t| !ranges.iter().any(|r|
+                                r.target_urls.contains(&t.url)));
+                        }
                         let status_json = StatusSerializer
                             .serialize(&status, true)
                             .to_string();
@@ -52,15 +109,252 @@ impl StatusHandler {
                         res.write_all(status_json.as_bytes()).unwrap();
                         res.end().unwrap();
                     }
+                    (&hyper::Get, "/metrics") => {
+                        let status = self.status_cache.read().unwrap().poll();
+                        let metrics_text = metrics::render(&status);
+
+                        res.headers_mut().set(ContentType::plaintext());
+
+                        let mut res = res.start().unwrap();
+                        res.write_all(metrics_text.as_bytes()).unwrap();
+                        res.end().unwrap();
+                    }
+                    (&hyper::Get, "/whoami") => {
+                        // Echo the nonce so that probes can tell that this
+                        // process answered their request.
+                        let whoami = Whoami {
+                            identity: self.identity.clone(),
+                            nonce: query_param(&query, "nonce")
+                                .map(|n| n.to_string()),
+                            remote_address: remote_address,
+                        };
+                        let whoami_json = WhoamiSerializer
+                            .serialize(&whoami, true)
+                            .to_string();
+
+                        res.headers_mut().set(ContentType::json());
+
+                        let mut res = res.start().unwrap();
+                        res.write_all(whoami_json.as_bytes()).unwrap();
+                        res.end().unwrap();
+                    }
+                    (&hyper::Get, throughput::DOWNLOAD_PATH) => {
+                        self.get_throughput_download(&query, res);
+                    }
+                    (&hyper::Post, throughput::UPLOAD_PATH) => {
+                        self.post_throughput_upload(&mut req, res);
+                    }
+                    (&hyper::Post, path)
+                            if target_id(path, TRACE_SUFFIX).is_some() => {
+                        let id = target_id(path, TRACE_SUFFIX).unwrap();
+                        self.post_trace(id, &query, res);
+                    }
+                    (&hyper::Post, path)
+                            if target_id(path, THROUGHPUT_SUFFIX).is_some() => {
+                        let id = target_id(path, THROUGHPUT_SUFFIX).unwrap();
+                        self.post_throughput(id, &query, res);
+                    }
                     _ => {
-                        // Anything but GET /status is invalid.
+                        // Anything else is invalid.
                         *res.status_mut() = hyper::NotFound;
                         return;
                     }
-                },
-                _ => {
-                    return;
                 }
+            },
+            _ => {
+                return;
+            }
         };
     }
+
+    /// The target with the URL, for on-demand tests: with the settings it
+    /// is probed with, or just the defaults if it has not been probed yet.
+    fn target(&self, url: &str) -> TargetSpec {
+        if let Some(target) = self.status_cache.read().unwrap().spec(url) {
+            return target;
+        }
+        let target = TargetSpec::new(url).with_default_source(&self.source);
+        match self.nameserver {
+            Some(ref nameserver) => target.with_default_dns_server(nameserver),
+            None => target,
+        }
+    }
+
+    /// Traces the path to the target with the percent-encoded URL `id`.
+    fn post_trace(&self, id: &str, query: &Query, mut res: Response<Fresh>) {
+        let url = decode_segment(id);
+        if !self.status_cache.read().unwrap().contains(&url) {
+            *res.status_mut() = hyper::NotFound;
+            return;
+        }
+        let max_hops = query_param(query, "max_hops")
+            .and_then(|m| m.parse::<u8>().ok())
+            .map(|m| cmp::max(1, cmp::min(m, trace::MAX_HOPS)))
+            .unwrap_or(trace::DEFAULT_MAX_HOPS);
+        let _slot = match self.traces.try_start() {
+            Some(slot) => slot,
+            None => return plaintext_error(
+                res,
+                StatusCode::TooManyRequests,
+                format!("At most {} traces may run at once",
+                        trace::MAX_CONCURRENT_TRACES)),
+        };
+
+        let target = self.target(&url);
+        match trace::trace(&target,
+                           max_hops,
+                           trace::DEFAULT_HOP_TIMEOUT_MS) {
+            Ok(trace) => {
+                let trace_json = TraceSerializer
+                    .serialize(&trace, true)
+                    .to_string();
+
+                res.headers_mut().set(ContentType::json());
+
+                let mut res = res.start().unwrap();
+                res.write_all(trace_json.as_bytes()).unwrap();
+                res.end().unwrap();
+            },
+            Err(message) => {
+                warn!("{}", message);
+                plaintext_error(res, StatusCode::InternalServerError, message);
+            },
+        }
+    }
+
+    /// Streams filler to the client for the requested number of seconds.
+    fn get_throughput_download(&self, query: &Query, mut res: Response<Fresh>) {
+        let _slot = match self.throughput_tests.try_start() {
+            Some(slot) => slot,
+            None => return too_many_tests(res),
+        };
+        let seconds = query_param(query, "seconds")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(throughput::DEFAULT_SECONDS);
+
+        res.headers_mut().set(ContentType::plaintext());
+
+        let mut res = res.start().unwrap();
+        match throughput::send_filler(&mut res, seconds) {
+            Ok(bytes) => info!("Sent {} bytes of filler", bytes),
+            Err(cause) => {
+                warn!("Failed to send filler: {}", cause);
+                return;
+            },
+        }
+        let _ = res.end();
+    }
+
+    /// Reads the uploaded filler and reports how much arrived how quickly.
+    fn post_throughput_upload(&self,
+                              req: &mut Request,
+                              mut res: Response<Fresh>) {
+        // The request body may be left unread, so the connection cannot be
+        // reused.
+        res.headers_mut().set(Connection::close());
+        let _slot = match self.throughput_tests.try_start() {
+            Some(slot) => slot,
+            None => return too_many_tests(res),
+        };
+        let start_ns = time::precise_time_ns();
+        let bytes = match throughput::drain(req) {
+            Ok(bytes) => bytes,
+            Err(cause) => {
+                warn!("Failed to read uploaded filler: {}", cause);
+                *res.status_mut() = StatusCode::BadRequest;
+                return;
+            },
+        };
+        let result =
+            Throughput::new(Direction::Upload, bytes, elapsed_ms(start_ns));
+
+        let throughput_json = ThroughputSerializer
+            .serialize(&result, true)
+            .to_string();
+
+        res.headers_mut().set(ContentType::json());
+
+        let mut res = res.start().unwrap();
+        res.write_all(throughput_json.as_bytes()).unwrap();
+        res.end().unwrap();
+    }
+
+    /// Measures the throughput to the target with the percent-encoded URL
+    /// `id`, which must be another star-probe, and records the result in
+    /// its status.
+    fn post_throughput(&self,
+                       id: &str,
+                       query: &Query,
+                       mut res: Response<Fresh>) {
+        let url = decode_segment(id);
+        if !self.status_cache.read().unwrap().contains(&url) {
+            *res.status_mut() = hyper::NotFound;
+            return;
+        }
+        let _slot = match self.throughput_tests.try_start() {
+            Some(slot) => slot,
+            None => return too_many_tests(res),
+        };
+        let direction = match Direction::parse(
+                query_param(query, "direction").unwrap_or("download")) {
+            Ok(direction) => direction,
+            Err(message) => return plaintext_error(res,
+                                                   StatusCode::BadRequest,
+                                                   message),
+        };
+        let seconds = query_param(query, "seconds")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(throughput::DEFAULT_SECONDS);
+
+        let target = self.target(&url);
+        match throughput::measure(&target, direction, seconds) {
+            Ok(result) => {
+                self.status_cache.write().unwrap()
+                    .record_throughput(&url, &result);
+
+                let throughput_json = ThroughputSerializer
+                    .serialize(&result, true)
+                    .to_string();
+
+                res.headers_mut().set(ContentType::json());
+
+                let mut res = res.start().unwrap();
+                res.write_all(throughput_json.as_bytes()).unwrap();
+                res.end().unwrap();
+            },
+            Err(message) => {
+                warn!("{}", message);
+                plaintext_error(res, StatusCode::InternalServerError, message);
+            },
+        }
+    }
+}
+
+/// The encoded target id of paths like `/targets/{id}/trace`.
+fn target_id<'a>(path: &'a str, suffix: &str) -> Option<&'a str> {
+    if path.starts_with(TARGETS_PREFIX) &&
+       path.ends_with(suffix) &&
+       path.len() > TARGETS_PREFIX.len() + suffix.len() {
+        Some(&path[TARGETS_PREFIX.len()..path.len() - suffix.len()])
+    } else {
+        None
+    }
+}
+
+fn plaintext_error(mut res: Response<Fresh>,
+                   status: StatusCode,
+                   message: String) {
+    *res.status_mut() = status;
+    res.headers_mut().set(ContentType::plaintext());
+    let mut res = res.start().unwrap();
+    res.write_all(message.as_bytes()).unwrap();
+    res.end().unwrap();
+}
+
+/// Responds that the throughput test cannot run now.
+fn too_many_tests(res: Response<Fresh>) {
+    plaintext_error(res,
+                    StatusCode::TooManyRequests,
+                    format!("At most {} throughput tests may run at once",
+                            throughput::MAX_CONCURRENT_TESTS));
 }
@@ -1,2 +1,8 @@
+pub mod heartbeat;
 pub mod http;
+pub mod protocol;
+pub mod range;
 pub mod status;
+pub mod target;
+pub mod throughput;
+pub mod trace;
@@ -0,0 +1,171 @@
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::http::{ConnectTiming, Connector};
+use common::identity::REMOTE_ADDRESS_HEADER;
+use probe::protocol::{abbreviate, elapsed_ms, ms_between, Failure, Outcome,
+    Prober, Timing, DEFAULT_TIMEOUT_MS};
+use probe::target::{HttpOptions, TargetSpec};
+
+use hyper::Client;
+use hyper::client::Response;
+use hyper::header::{Connection, Headers};
+use hyper::method::Method;
+use time;
+
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Probes `http://` and `https://` targets with a request, by default a
+/// `GET`.  Any HTTP response counts as reachable unless the target expects
+/// particular status codes or body content, in which case other responses
+/// are reported as a mismatch.
+///
+/// Each probe records the time spent resolving the host, connecting, in
+/// the TLS handshake and waiting for the response headers.
+pub struct HttpProber;
+
+impl HttpProber {
+    pub fn new() -> HttpProber {
+        HttpProber
+    }
+}
+
+impl Prober for HttpProber {
+    fn probe(&self, target: &TargetSpec) -> Outcome {
+        let default_options = HttpOptions {
+            method: None,
+            headers: None,
+            body: None,
+            expect_status: None,
+            expect_body: None,
+        };
+        let options = target.http.as_ref().unwrap_or(&default_options);
+
+        let method = match options.method {
+            Some(ref method) => match Method::from_str(method) {
+                Ok(method) => method,
+                Err(_) => return Outcome::failed(Failure::Invalid(
+                    format!("Invalid HTTP method [{}]", method))),
+            },
+            None => Method::Get,
+        };
+
+        let mut headers = Headers::new();
+        if let Some(ref extra_headers) = options.headers {
+            for (name, value) in extra_headers {
+                headers.set_raw(name.clone(),
+                                vec!(value.clone().into_bytes()));
+            }
+        }
+        headers.set(Connection::close());
+
+        let start_ns = time::precise_time_ns();
+        let resolution = match target.resolution() {
+            Ok(resolution) => resolution,
+            Err(cause) => return Outcome::failed(Failure::Invalid(cause)),
+        };
+        let source = target.source();
+        let timeout = Duration::from_millis(
+            target.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+        let connect_timing = Arc::new(Mutex::new(None));
+        let client = Client::with_connector(
+            Connector::new(target.family, source.clone(), resolution)
+                .timeout(timeout)
+                .capture_timing(connect_timing.clone()));
+        let mut request = client.request(method, &target.url[..])
+            .headers(headers);
+        if let Some(ref body) = options.body {
+            request = request.body(&body[..]);
+        }
+
+        let mut response = match request.send() {
+            Ok(response) => response,
+            Err(cause) => {
+                // Report the address and phases reached before the failure.
+                let connect_timing = connect_timing.lock().unwrap().clone();
+                let mut outcome = Outcome::unreachable(format!("{}", cause));
+                outcome.source = source.used(
+                    connect_timing.as_ref().and_then(|t| t.local_ip));
+                outcome.resolved_address =
+                    connect_timing.as_ref().and_then(|t| t.remote_ip);
+                outcome.timing =
+                    connect_timing.map(|t| timing_of(&t, None, start_ns));
+                return outcome;
+            },
+        };
+        let connect_timing = connect_timing.lock().unwrap().clone();
+        let ttfb_ms = connect_timing.as_ref()
+            .and_then(|t| t.ready_ns)
+            .map(elapsed_ms);
+
+        let mut outcome = check_response(options, &mut response);
+        outcome.observed_address = observed_address(&response);
+        outcome.source =
+            source.used(connect_timing.as_ref().and_then(|t| t.local_ip));
+        outcome.resolved_address =
+            connect_timing.as_ref().and_then(|t| t.remote_ip);
+        outcome.timing =
+            connect_timing.map(|t| timing_of(&t, ttfb_ms, start_ns));
+        outcome
+    }
+}
+
+/// The timing of a probe that started at `start_ns`, with the phases of its
+/// connection.
+fn timing_of(connect: &ConnectTiming,
+             ttfb_ms: Option<f64>,
+             start_ns: u64) -> Timing {
+    Timing {
+        dns_ms: phase_ms(Some(connect.start_ns), connect.resolved_ns),
+        connect_ms: phase_ms(connect.resolved_ns, connect.connected_ns),
+        tls_ms: phase_ms(connect.connected_ns, connect.tls_ns),
+        ttfb_ms: ttfb_ms,
+        total_ms: elapsed_ms(start_ns),
+    }
+}
+
+/// Milliseconds between the ends of two phases, if both completed.
+fn phase_ms(start_ns: Option<u64>, end_ns: Option<u64>) -> Option<f64> {
+    match (start_ns, end_ns) {
+        (Some(start_ns), Some(end_ns)) => Some(ms_between(start_ns, end_ns)),
+        _ => None,
+    }
+}
+
+/// Returns the source address reported by a star server, if any.
+pub fn observed_address(response: &Response) -> Option<String> {
+    response.headers.get_raw(REMOTE_ADDRESS_HEADER)
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+fn check_response(options: &HttpOptions, response: &mut Response) -> Outcome {
+    if let Some(ref expect_status) = options.expect_status {
+        let status_code = response.status.to_u16();
+        if !expect_status.contains(&status_code) {
+            return Outcome::failed(Failure::Mismatch(
+                format!("Expected status {:?} but received [{}]",
+                        expect_status,
+                        response.status)));
+        }
+    }
+
+    if let Some(ref expect_body) = options.expect_body {
+        let mut body = String::new();
+        if let Err(cause) = response.by_ref().take(MAX_BODY_BYTES)
+            .read_to_string(&mut body) {
+            return Outcome::unreachable(
+                format!("Failed to read response body: {}", cause));
+        }
+        if !expect_body.matches(&body) {
+            return Outcome::failed(Failure::Mismatch(
+                format!("Expected body {} but received {}",
+                        expect_body.describe(),
+                        abbreviate(&body))));
+        }
+    }
+
+    Outcome::reachable()
+}
@@ -0,0 +1,170 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::Duration;
+
+use probe::protocol::{elapsed_ms, Failure, Outcome, Prober,
+    DEFAULT_TIMEOUT_MS};
+use probe::target::TargetSpec;
+
+use libc;
+use time;
+
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+const PAYLOAD: &'static [u8] = b"star";
+
+static SEQUENCE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Probes `icmp://host` targets with an ICMP echo request, using the
+/// unprivileged ICMP datagram sockets of Linux.  These are only permitted
+/// for the groups in the `net.ipv4.ping_group_range` sysctl; otherwise the
+/// target is reported as invalid.
+pub struct IcmpProber {
+    timeout_ms: u64,
+}
+
+impl IcmpProber {
+    pub fn new() -> IcmpProber {
+        IcmpProber { timeout_ms: DEFAULT_TIMEOUT_MS, }
+    }
+
+    pub fn with_timeout_ms(timeout_ms: u64) -> IcmpProber {
+        IcmpProber { timeout_ms: timeout_ms, }
+    }
+}
+
+impl Prober for IcmpProber {
+    fn probe(&self, target: &TargetSpec) -> Outcome {
+        let addr = match target.ip_addrs() {
+            Ok(ref ips) if !ips.is_empty() => SocketAddr::new(ips[0], 0),
+            Ok(_) => return Outcome::failed(Failure::Invalid(
+                format!("No addresses for [{}]", target.url))),
+            Err(cause) => return Outcome::failed(Failure::Invalid(cause)),
+        };
+        let mut outcome = self.echo(target, addr);
+        outcome.resolved_address = Some(addr.ip());
+        outcome
+    }
+}
+
+impl IcmpProber {
+    /// Sends an echo request to the address and waits for the reply.
+    fn echo(&self, target: &TargetSpec, addr: SocketAddr) -> Outcome {
+        let socket = match open_socket(&addr) {
+            Ok(socket) => socket,
+            Err(failure) => return Outcome::failed(failure),
+        };
+        let source = target.source();
+        if let Err(cause) = source.bind(&socket, &addr) {
+            return Outcome::failed(Failure::Invalid(
+                format!("Failed to bind to the source: {}", cause)));
+        }
+        let timeout_ms = target.timeout_ms.unwrap_or(self.timeout_ms);
+
+        let sequence =
+            (SEQUENCE.fetch_add(1, Ordering::SeqCst) & 0xffff) as u16;
+        let (request_type, reply_type) = if addr.is_ipv4() {
+            (ICMP_ECHO_REQUEST, ICMP_ECHO_REPLY)
+        } else {
+            (ICMPV6_ECHO_REQUEST, ICMPV6_ECHO_REPLY)
+        };
+        let request = echo_request(request_type, sequence);
+
+        let start_ns = time::precise_time_ns();
+        if let Err(cause) = socket.send_to(&request, addr) {
+            return Outcome::unreachable(format!("{}: {}", addr, cause));
+        }
+
+        // The kernel delivers only the replies to this socket's echo
+        // requests; skip those to earlier requests.
+        let deadline_ns = start_ns + timeout_ms * 1_000_000;
+        let mut buf = [0u8; 1500];
+        loop {
+            let now_ns = time::precise_time_ns();
+            if now_ns >= deadline_ns {
+                return Outcome::unreachable(
+                    format!("{}: no echo reply", addr));
+            }
+            let _ = socket.set_read_timeout(Some(Duration::from_millis(
+                (deadline_ns - now_ns) / 1_000_000 + 1)));
+            match socket.recv_from(&mut buf) {
+                Ok((n, _)) if n >= 8 &&
+                    buf[0] == reply_type &&
+                    read_u16(&buf, 6) == sequence => {
+                    let mut outcome = Outcome::reachable();
+                    outcome.rtt_ms = Some(elapsed_ms(start_ns));
+                    outcome.source = source.used(
+                        socket.local_addr().ok().map(|addr| addr.ip()));
+                    return outcome;
+                },
+                Ok(_) => continue,
+                Err(cause) => return Outcome::unreachable(
+                    format!("{}: {}", addr, cause)),
+            }
+        }
+    }
+}
+
+/// Opens an unprivileged ICMP datagram socket for the address family.
+fn open_socket(addr: &SocketAddr) -> Result<UdpSocket, Failure> {
+    let (family, protocol) = if addr.is_ipv4() {
+        (libc::AF_INET, libc::IPPROTO_ICMP)
+    } else {
+        (libc::AF_INET6, libc::IPPROTO_ICMPV6)
+    };
+    let fd = unsafe { libc::socket(family, libc::SOCK_DGRAM, protocol) };
+    if fd < 0 {
+        let cause = io::Error::last_os_error();
+        let message = match cause.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EPERM) => format!(
+                "Unprivileged ICMP sockets are not permitted for this \
+                 process's group; see the net.ipv4.ping_group_range \
+                 sysctl ({})", cause),
+            Some(libc::EPROTONOSUPPORT) | Some(libc::EAFNOSUPPORT) => format!(
+                "Unprivileged ICMP sockets are not supported by this \
+                 system ({})", cause),
+            _ => format!("Failed to open ICMP socket: {}", cause),
+        };
+        return Err(Failure::Invalid(message));
+    }
+    // The socket is a datagram socket, so it can be used like a UDP one.
+    Ok(unsafe { UdpSocket::from_raw_fd(fd) })
+}
+
+/// An echo request; the kernel sets the identifier of unprivileged ICMP
+/// sockets itself.
+fn echo_request(request_type: u8, sequence: u16) -> Vec<u8> {
+    let mut packet = vec!(request_type, 0, 0, 0, 0, 0,
+                          (sequence >> 8) as u8, sequence as u8);
+    packet.extend(PAYLOAD.iter().cloned());
+    let checksum = checksum(&packet);
+    packet[2] = (checksum >> 8) as u8;
+    packet[3] = checksum as u8;
+    packet
+}
+
+/// The Internet checksum of the packet.
+fn checksum(packet: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in packet.chunks(2) {
+        let word = if chunk.len() == 2 {
+            ((chunk[0] as u32) << 8) | chunk[1] as u32
+        } else {
+            (chunk[0] as u32) << 8
+        };
+        sum += word;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    ((buf[offset] as u16) << 8) | buf[offset + 1] as u16
+}
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::io::Read;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::Duration;
+
+use common::http::Connector;
+use probe::protocol::{Failure, Outcome, Prober, Responder,
+    DEFAULT_TIMEOUT_MS};
+use probe::target::TargetSpec;
+
+use hyper::Client;
+use hyper::header::Connection;
+use rustc_serialize::json::Json;
+use time;
+
+static NONCE_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Probes `star://host:port` targets, which must be star processes, by
+/// requesting `GET /whoami?nonce=...` and checking that the responder echoes
+/// the nonce and has the identity expected for the target.  Any other
+/// responder is reported as misrouted.
+///
+/// Without an expected identity, any star process that echoes the nonce
+/// will do, so a warning is logged once per such target.  The identity of
+/// the responder is reported either way.
+pub struct IdentityProber {
+    unverified: Mutex<HashSet<String>>,
+}
+
+impl IdentityProber {
+    pub fn new() -> IdentityProber {
+        IdentityProber { unverified: Mutex::new(HashSet::new()), }
+    }
+
+    fn warn_if_unverified(&self, target: &TargetSpec) {
+        let verified = target.identity.as_ref()
+            .map(|i| i.id.is_some() || i.hostname.is_some())
+            .unwrap_or(false);
+        if !verified &&
+            self.unverified.lock().unwrap().insert(target.url.clone()) {
+            warn!("No identity is expected for [{}], so any star process \
+                   may answer it",
+                  target.url);
+        }
+    }
+}
+
+impl Prober for IdentityProber {
+    fn probe(&self, target: &TargetSpec) -> Outcome {
+        self.warn_if_unverified(target);
+        let authority = match target.authority() {
+            Some(authority) => authority,
+            None => return Outcome::failed(Failure::Invalid(
+                format!("Invalid target URL [{}]", target.url))),
+        };
+        let resolution = match target.resolution() {
+            Ok(resolution) => resolution,
+            Err(cause) => return Outcome::failed(Failure::Invalid(cause)),
+        };
+        let nonce = new_nonce();
+        let whoami_url = format!("http://{}/whoami?nonce={}", authority, nonce);
+
+        let timeout = Duration::from_millis(
+            target.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+        let client = Client::with_connector(
+            Connector::new(target.family, target.source(), resolution)
+                .timeout(timeout));
+        let mut response = match client.get(&whoami_url)
+            .header(Connection::close())
+            .send() {
+            Ok(response) => response,
+            Err(cause) => return Outcome::unreachable(format!("{}", cause)),
+        };
+
+        let mut body = String::new();
+        if let Err(cause) = response.read_to_string(&mut body) {
+            return Outcome::unreachable(
+                format!("Failed to read response body: {}", cause));
+        }
+        let whoami = match Json::from_str(&body).ok()
+            .and_then(|json| json.find("whoami").cloned()) {
+            Some(whoami) => whoami,
+            None => return Outcome::failed(Failure::Misrouted(
+                format!("[{}] is not a star process", whoami_url))),
+        };
+
+        let field = |name: &str| whoami.find(name)
+            .and_then(|value| value.as_string())
+            .unwrap_or("")
+            .to_string();
+
+        if field("nonce") != nonce {
+            return Outcome::failed(Failure::Misrouted(format!(
+                "Expected nonce [{}] but received [{}] from [{}]",
+                nonce, field("nonce"), field("id"))));
+        }
+        if let Some(ref expected) = target.identity {
+            if let Some(ref id) = expected.id {
+                if field("id") != *id {
+                    return Outcome::failed(Failure::Misrouted(format!(
+                        "Expected id [{}] but [{}] answered",
+                        id, field("id"))));
+                }
+            }
+            if let Some(ref hostname) = expected.hostname {
+                if field("hostname") != *hostname {
+                    return Outcome::failed(Failure::Misrouted(format!(
+                        "Expected hostname [{}] but [{}] answered",
+                        hostname, field("hostname"))));
+                }
+            }
+        }
+
+        let mut outcome = Outcome::reachable();
+        outcome.responder = Some(Responder {
+            id: field("id"),
+            hostname: field("hostname"),
+        });
+        let observed_address = field("remoteAddress");
+        if !observed_address.is_empty() {
+            outcome.observed_address = Some(observed_address);
+        }
+        outcome
+    }
+}
+
+fn new_nonce() -> String {
+    let count = NONCE_COUNTER.fetch_add(1, Ordering::SeqCst);
+    format!("{:x}{:04x}", time::precise_time_ns(), count & 0xffff)
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use common::net::Source;
+use probe::target::TargetSpec;
+
+use time;
+
+pub mod http;
+pub mod icmp;
+pub mod identity;
+pub mod mtu;
+pub mod tcp;
+pub mod udp;
+
+/// The default time to wait for a target to respond.
+pub const DEFAULT_TIMEOUT_MS: u64 = 3000;
+
+/// Why a probe failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    /// The target could not be reached.
+    Unreachable(String),
+    /// The target was reached, but did not respond as expected.
+    Mismatch(String),
+    /// A responder other than the expected one answered, e.g. because a
+    /// load balancer or NAT routed the probe elsewhere.
+    Misrouted(String),
+    /// The target could not be probed, e.g. because of an unsupported URL
+    /// scheme.
+    Invalid(String),
+}
+
+impl Failure {
+    /// A short, stable name for the kind of failure.
+    pub fn reason(&self) -> &'static str {
+        match *self {
+            Failure::Unreachable(_) => "unreachable",
+            Failure::Mismatch(_) => "mismatch",
+            Failure::Misrouted(_) => "misrouted",
+            Failure::Invalid(_) => "invalid",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match *self {
+            Failure::Unreachable(ref message) |
+            Failure::Mismatch(ref message) |
+            Failure::Misrouted(ref message) |
+            Failure::Invalid(ref message) => message,
+        }
+    }
+}
+
+/// Where the time of a request-response probe went, in milliseconds.  The
+/// phases a failed probe did not complete are `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timing {
+    /// Resolving the host name to addresses.
+    pub dns_ms: Option<f64>,
+    /// Establishing the TCP connection.
+    pub connect_ms: Option<f64>,
+    /// The TLS handshake, for `https://` targets.
+    pub tls_ms: Option<f64>,
+    /// From sending the request until the response headers arrived.
+    pub ttfb_ms: Option<f64>,
+    /// The whole probe, including reading the response body if needed.
+    pub total_ms: f64,
+}
+
+/// The identity of the star process that answered a `star://` probe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Responder {
+    pub id: String,
+    pub hostname: String,
+}
+
+/// The outcome of a single probe of a target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub failure: Option<Failure>,
+    /// Round trip time of a successful probe, in milliseconds.
+    pub rtt_ms: Option<f64>,
+    /// The source address of the probe as observed by the target, when the
+    /// target is a star process that reports it.
+    pub observed_address: Option<String>,
+    /// The phases of the probe, for probers that measure them.
+    pub timing: Option<Timing>,
+    /// The largest IP packet that reached the target and back, in bytes.
+    pub path_mtu: Option<u32>,
+    /// The local address and/or device the probe left from, when either was
+    /// chosen or is known.
+    pub source: Option<Source>,
+    /// The address the host of the target resolved to and was probed at,
+    /// for probers that resolve it.
+    pub resolved_address: Option<IpAddr>,
+    /// The identity of the star process that answered, for probers that
+    /// ask for it.
+    pub responder: Option<Responder>,
+}
+
+impl Outcome {
+    pub fn reachable() -> Outcome {
+        Outcome {
+            failure: None,
+            rtt_ms: None,
+            observed_address: None,
+            timing: None,
+            path_mtu: None,
+            source: None,
+            resolved_address: None,
+            responder: None,
+        }
+    }
+
+    pub fn failed(failure: Failure) -> Outcome {
+        Outcome {
+            failure: Some(failure),
+            rtt_ms: None,
+            observed_address: None,
+            timing: None,
+            path_mtu: None,
+            source: None,
+            resolved_address: None,
+            responder: None,
+        }
+    }
+
+    pub fn unreachable(message: String) -> Outcome {
+        Outcome::failed(Failure::Unreachable(message))
+    }
+
+    pub fn is_reachable(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Probes targets of one or more URL schemes.
+pub trait Prober: Send + Sync {
+    fn probe(&self, target: &TargetSpec) -> Outcome;
+}
+
+/// Probers keyed by the URL scheme of the targets they probe.
+pub struct Registry {
+    probers: HashMap<String, Arc<Prober>>,
+}
+
+impl Registry {
+    /// A registry without any probers.
+    pub fn new() -> Registry {
+        Registry { probers: HashMap::new(), }
+    }
+
+    /// A registry with the probers built into star.
+    pub fn with_defaults() -> Registry {
+        let mut registry = Registry::new();
+        let http_prober = Arc::new(http::HttpProber::new());
+        registry.register("http", http_prober.clone());
+        registry.register("https", http_prober);
+        registry.register("icmp", Arc::new(icmp::IcmpProber::new()));
+        registry.register("mtu", Arc::new(mtu::MtuProber::new()));
+        registry.register("star", Arc::new(identity::IdentityProber::new()));
+        registry.register("tcp", Arc::new(tcp::TcpProber::new()));
+        registry.register("udp", Arc::new(udp::UdpProber::new()));
+        registry
+    }
+
+    /// Registers the prober for a URL scheme, replacing any existing one.
+    pub fn register(&mut self, scheme: &str, prober: Arc<Prober>) {
+        self.probers.insert(scheme.to_lowercase(), prober);
+    }
+
+    pub fn get(&self, scheme: &str) -> Option<Arc<Prober>> {
+        self.probers.get(&scheme.to_lowercase()).cloned()
+    }
+
+    pub fn schemes(&self) -> Vec<String> {
+        self.probers.keys().cloned().collect()
+    }
+
+    /// Probes the target with the prober registered for its scheme.  The
+    /// elapsed time is recorded as the round trip time of a successful probe
+    /// unless the prober measured it, and the target's source as the source
+    /// of the probe unless the prober found the one actually used.
+    pub fn probe(&self, target: &TargetSpec) -> Outcome {
+        let prober = match target.scheme().and_then(|s| self.get(s)) {
+            Some(prober) => prober,
+            None => return Outcome::failed(Failure::Invalid(
+                format!("No prober for target [{}]", target.url))),
+        };
+
+        let start_ns = time::precise_time_ns();
+        let mut outcome = prober.probe(target);
+        if outcome.is_reachable() && outcome.rtt_ms.is_none() {
+            outcome.rtt_ms = Some(elapsed_ms(start_ns));
+        }
+        if outcome.source.is_none() {
+            outcome.source = target.source().used(None);
+        }
+        outcome
+    }
+}
+
+/// Shortens received text for inclusion in a failure message.
+pub fn abbreviate(text: &str) -> String {
+    const MAX_CHARS: usize = 200;
+    if text.chars().count() <= MAX_CHARS {
+        return format!("{:?}", text);
+    }
+    let prefix: String = text.chars().take(MAX_CHARS).collect();
+    format!("{:?}...", prefix)
+}
+
+/// Milliseconds elapsed since the supplied `time::precise_time_ns()`.
+pub fn elapsed_ms(start_ns: u64) -> f64 {
+    ms_between(start_ns, time::precise_time_ns())
+}
+
+/// Milliseconds between two `time::precise_time_ns()` readings.
+pub fn ms_between(start_ns: u64, end_ns: u64) -> f64 {
+    (end_ns - start_ns) as f64 / 1e6
+}
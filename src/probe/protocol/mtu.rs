@@ -0,0 +1,206 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use common::net;
+use common::sockopt::{get_int_option, set_int_option};
+use probe::protocol::{Failure, Outcome, Prober};
+use probe::target::TargetSpec;
+
+use libc;
+use time;
+
+// Linux socket options for path MTU discovery.
+const IP_MTU_DISCOVER: libc::c_int = 10;
+const IP_MTU: libc::c_int = 14;
+const IPV6_MTU_DISCOVER: libc::c_int = 23;
+const IPV6_MTU: libc::c_int = 24;
+const PMTUDISC_DO: libc::c_int = 2;
+
+/// Prefix of the datagrams sent by `mtu://` probes, which star-target
+/// echoes back unchanged.
+pub const MTU_PROBE_PREFIX: &'static [u8] = b"star-mtu ";
+
+/// The minimum MTU of each IP version, which every path must carry.
+pub const IPV4_MIN_MTU: u32 = 576;
+pub const IPV6_MIN_MTU: u32 = 1280;
+
+/// The default time to wait for the reply to each datagram.
+const DEFAULT_DATAGRAM_TIMEOUT_MS: u64 = 1000;
+
+/// The search gives up after this many datagram timeouts in total.
+const MAX_SEARCH_TIMEOUTS: u64 = 8;
+
+/// Probes `mtu://host:port` targets, which must echo UDP datagrams back,
+/// e.g. star-target, for the largest IP packet that gets through.  The
+/// datagrams are sent with the don't-fragment flag set, searching between
+/// the minimum MTU of the IP version and the MTU of the local route.
+///
+/// A size that goes unanswered is tried once more before it is taken not
+/// to fit, but a datagram that is lost twice for another reason still makes
+/// the path MTU look smaller than it is.  A search that runs out of time
+/// reports the largest size found to fit so far.
+pub struct MtuProber {
+    timeout_ms: u64,
+}
+
+impl MtuProber {
+    pub fn new() -> MtuProber {
+        MtuProber { timeout_ms: DEFAULT_DATAGRAM_TIMEOUT_MS, }
+    }
+
+    pub fn with_timeout_ms(timeout_ms: u64) -> MtuProber {
+        MtuProber { timeout_ms: timeout_ms, }
+    }
+}
+
+impl Prober for MtuProber {
+    fn probe(&self, target: &TargetSpec) -> Outcome {
+        let addr = match target.socket_addrs() {
+            Ok(ref addrs) if !addrs.is_empty() => addrs[0],
+            Ok(_) => return Outcome::failed(Failure::Invalid(
+                format!("No addresses for [{}]", target.url))),
+            Err(cause) => return Outcome::failed(Failure::Invalid(cause)),
+        };
+        let mut outcome = self.find_path_mtu(target, addr);
+        outcome.resolved_address = Some(addr.ip());
+        outcome
+    }
+}
+
+impl MtuProber {
+    /// Searches for the largest packet that reaches the address and back.
+    fn find_path_mtu(&self, target: &TargetSpec, addr: SocketAddr)
+                     -> Outcome {
+        // Sizes are of whole IP packets; the IP and UDP headers take up
+        // `overhead` bytes of each.
+        let (level, discover, mtu_option, overhead, min_mtu) =
+            if addr.is_ipv4() {
+                (libc::IPPROTO_IP, IP_MTU_DISCOVER, IP_MTU, 28, IPV4_MIN_MTU)
+            } else {
+                (libc::IPPROTO_IPV6, IPV6_MTU_DISCOVER, IPV6_MTU, 48,
+                 IPV6_MIN_MTU)
+            };
+
+        let source = target.source();
+        let socket = match net::udp_socket(&addr, &source)
+            .and_then(|socket| socket.connect(addr).map(|_| socket)) {
+            Ok(socket) => socket,
+            Err(cause) => return Outcome::failed(Failure::Invalid(
+                format!("Failed to open UDP socket: {}", cause))),
+        };
+        if let Err(cause) =
+            set_int_option(socket.as_raw_fd(), level, discover, PMTUDISC_DO) {
+            return Outcome::failed(Failure::Invalid(
+                format!("Failed to set the don't-fragment flag: {}", cause)));
+        }
+
+        let local_mtu = get_int_option(socket.as_raw_fd(), level, mtu_option)
+            .map(|mtu| mtu as u32)
+            .unwrap_or(1500);
+        let options = target.mtu.as_ref();
+        let max_mtu = options.and_then(|o| o.max)
+            .map(|max| if max < local_mtu { max } else { local_mtu })
+            .unwrap_or(local_mtu);
+        if max_mtu < min_mtu {
+            return Outcome::failed(Failure::Invalid(format!(
+                "The largest MTU to try, {}, is below the minimum of {} for \
+                 [{}]",
+                max_mtu, min_mtu, addr)));
+        }
+
+        let timeout_ms = target.timeout_ms.unwrap_or(self.timeout_ms);
+        let deadline_ns = time::precise_time_ns() +
+            MAX_SEARCH_TIMEOUTS * timeout_ms * 1_000_000;
+        let try_mtu = |mtu: u32| {
+            let size = (mtu - overhead) as usize;
+            match fits(&socket, size, timeout_ms) {
+                // The datagram or its echo may have been lost for another
+                // reason, so try once more.
+                Ok(false) => fits(&socket, size, timeout_ms),
+                result => result,
+            }
+        };
+
+        match try_mtu(min_mtu) {
+            Ok(true) => (),
+            Ok(false) => return Outcome::unreachable(format!(
+                "No reply to a {}-byte packet from [{}]", min_mtu, addr)),
+            Err(failure) => return Outcome::failed(failure),
+        }
+
+        // Search for the largest MTU that fits, knowing that `low` fits and
+        // `high` does not.
+        let mut low = min_mtu;
+        let mut high = max_mtu + 1;
+        match try_mtu(max_mtu) {
+            Ok(true) => low = max_mtu,
+            Ok(false) => high = max_mtu,
+            Err(failure) => return Outcome::failed(failure),
+        }
+        while high - low > 1 {
+            if time::precise_time_ns() >= deadline_ns {
+                warn!("Gave up searching for the path MTU of [{}] between {} \
+                       and {}",
+                      addr, low, high);
+                break;
+            }
+            let mid = low + (high - low) / 2;
+            match try_mtu(mid) {
+                Ok(true) => low = mid,
+                Ok(false) => high = mid,
+                Err(failure) => return Outcome::failed(failure),
+            }
+        }
+
+        let mut outcome = match options.and_then(|o| o.expect_min) {
+            Some(expect_min) if low < expect_min =>
+                Outcome::failed(Failure::Mismatch(format!(
+                    "Expected a path MTU of at least {} but found {}",
+                    expect_min, low))),
+            _ => Outcome::reachable(),
+        };
+        outcome.path_mtu = Some(low);
+        outcome.source =
+            source.used(socket.local_addr().ok().map(|addr| addr.ip()));
+        outcome
+    }
+}
+
+/// Whether a datagram with a payload of the supplied size is echoed back
+/// within the timeout.
+fn fits(socket: &UdpSocket, size: usize, timeout_ms: u64)
+        -> Result<bool, Failure> {
+    let mut payload = MTU_PROBE_PREFIX.to_vec();
+    payload.resize(size, b'.');
+    payload.truncate(size);
+
+    match socket.send(&payload) {
+        Ok(_) => (),
+        // The packet is larger than the path MTU known to the kernel.
+        Err(ref cause) if cause.raw_os_error() == Some(libc::EMSGSIZE) =>
+            return Ok(false),
+        Err(cause) => return Err(Failure::Unreachable(format!("{}", cause))),
+    }
+
+    // Skip late replies to earlier datagrams of other sizes.
+    let deadline_ns = time::precise_time_ns() + timeout_ms * 1_000_000;
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let now_ns = time::precise_time_ns();
+        if now_ns >= deadline_ns {
+            return Ok(false);
+        }
+        let wait_ms = (deadline_ns - now_ns) / 1_000_000 + 1;
+        let _ = socket.set_read_timeout(Some(Duration::from_millis(wait_ms)));
+        match socket.recv(&mut buf) {
+            Ok(n) if n == size && buf[..n] == payload[..] => return Ok(true),
+            Ok(_) => continue,
+            Err(ref cause)
+                if cause.kind() == io::ErrorKind::ConnectionRefused =>
+                return Err(Failure::Unreachable(format!("{}", cause))),
+            Err(_) => return Ok(false),
+        }
+    }
+}
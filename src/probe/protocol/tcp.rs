@@ -0,0 +1,146 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use common::net;
+use probe::protocol::{abbreviate, elapsed_ms, Failure, Outcome, Prober,
+    DEFAULT_TIMEOUT_MS};
+use probe::target::{Expect, TargetSpec};
+
+use time;
+
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// Probes `tcp://host:port` targets by opening a connection.  If the target
+/// has a script, its bytes are sent once connected, and the response must
+/// meet its expectation within the timeout.
+pub struct TcpProber {
+    timeout_ms: u64,
+}
+
+impl TcpProber {
+    pub fn new() -> TcpProber {
+        TcpProber { timeout_ms: DEFAULT_TIMEOUT_MS, }
+    }
+
+    pub fn with_timeout_ms(timeout_ms: u64) -> TcpProber {
+        TcpProber { timeout_ms: timeout_ms, }
+    }
+}
+
+impl Prober for TcpProber {
+    fn probe(&self, target: &TargetSpec) -> Outcome {
+        let timeout_ms = target.timeout_ms.unwrap_or(self.timeout_ms);
+        let mut stream = match connect(target, timeout_ms) {
+            Ok(stream) => stream,
+            Err(failure) => return Outcome::failed(failure),
+        };
+        let local_ip = stream.local_addr().ok().map(|addr| addr.ip());
+        let remote_ip = stream.peer_addr().ok().map(|addr| addr.ip());
+        let mut outcome = self.run_script(&mut stream, target, timeout_ms);
+        outcome.source = target.source().used(local_ip);
+        outcome.resolved_address = remote_ip;
+        outcome
+    }
+}
+
+impl TcpProber {
+    /// Runs the target's script, if any, over the connection.
+    fn run_script(&self,
+                  stream: &mut TcpStream,
+                  target: &TargetSpec,
+                  timeout_ms: u64) -> Outcome {
+        let script = match target.tcp {
+            Some(ref script) => script,
+            None => return Outcome::reachable(),
+        };
+
+        let start_ns = time::precise_time_ns();
+        if let Some(ref send) = script.send {
+            if let Err(cause) = stream.write_all(send.as_bytes()) {
+                return Outcome::unreachable(
+                    format!("Failed to send to [{}]: {}", target.url, cause));
+            }
+        }
+
+        match script.expect {
+            Some(ref expect) =>
+                match read_expected(stream, expect, timeout_ms) {
+                    Ok(()) => {
+                        let mut outcome = Outcome::reachable();
+                        outcome.rtt_ms = Some(elapsed_ms(start_ns));
+                        outcome
+                    },
+                    Err(failure) => Outcome::failed(failure),
+                },
+            None => Outcome::reachable(),
+        }
+    }
+}
+
+/// Connects to the first address of the target that accepts a connection.
+pub fn connect(target: &TargetSpec, timeout_ms: u64)
+               -> Result<TcpStream, Failure> {
+    let addrs = try!(target.socket_addrs().map_err(Failure::Invalid));
+    let timeout = Duration::from_millis(timeout_ms);
+    let source = target.source();
+
+    let mut last_error = format!("No addresses for [{}]", target.url);
+    for addr in addrs {
+        match net::connect_from(&addr, &source, Some(timeout)) {
+            Ok(stream) => {
+                let _ = stream.set_read_timeout(Some(timeout));
+                let _ = stream.set_write_timeout(Some(timeout));
+                return Ok(stream);
+            },
+            Err(cause) => last_error = format!("{}: {}", addr, cause),
+        }
+    }
+    Err(Failure::Unreachable(last_error))
+}
+
+/// Reads from the stream until the received text meets the expectation,
+/// which fails with a mismatch if the text can no longer meet it, the
+/// stream ends, or the timeout elapses.
+pub fn read_expected(stream: &mut TcpStream, expect: &Expect, timeout_ms: u64)
+                     -> Result<(), Failure> {
+    let deadline_ns = time::precise_time_ns() + timeout_ms * 1_000_000;
+    let mut received: Vec<u8> = vec!();
+    let mut buf = [0u8; 4096];
+
+    loop {
+        let text = String::from_utf8_lossy(&received).into_owned();
+        if expect.matches(&text) {
+            return Ok(());
+        }
+        let mismatch = |reason: &str| Failure::Mismatch(
+            format!("Expected response {} but {}; received {}",
+                    expect.describe(),
+                    reason,
+                    abbreviate(&text)));
+
+        if !expect.may_match_later(&text) {
+            return Err(mismatch("it cannot match"));
+        }
+        if received.len() >= MAX_RESPONSE_BYTES {
+            return Err(mismatch("the response is too long"));
+        }
+
+        let now_ns = time::precise_time_ns();
+        if now_ns >= deadline_ns {
+            return Err(mismatch("timed out"));
+        }
+        let wait_ms = (deadline_ns - now_ns) / 1_000_000 + 1;
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(wait_ms)));
+
+        match stream.read(&mut buf) {
+            Ok(0) => return Err(mismatch("the connection was closed")),
+            Ok(n) => received.extend(buf[..n].iter().cloned()),
+            Err(ref cause) if cause.kind() == io::ErrorKind::WouldBlock ||
+                cause.kind() == io::ErrorKind::TimedOut =>
+                return Err(mismatch("timed out")),
+            Err(cause) => return Err(Failure::Unreachable(
+                format!("Failed to read the response: {}", cause))),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use common::net;
+use probe::protocol::{Failure, Outcome, Prober, DEFAULT_TIMEOUT_MS};
+use probe::target::TargetSpec;
+
+const PAYLOAD: &'static [u8] = b"star";
+
+/// Probes `udp://host:port` targets by sending a datagram and waiting for
+/// any reply, such as from an echo responder.  Since UDP is connectionless,
+/// a target that does not reply is indistinguishable from one that is
+/// unreachable.
+pub struct UdpProber {
+    timeout_ms: u64,
+}
+
+impl UdpProber {
+    pub fn new() -> UdpProber {
+        UdpProber { timeout_ms: DEFAULT_TIMEOUT_MS, }
+    }
+
+    pub fn with_timeout_ms(timeout_ms: u64) -> UdpProber {
+        UdpProber { timeout_ms: timeout_ms, }
+    }
+}
+
+impl Prober for UdpProber {
+    fn probe(&self, target: &TargetSpec) -> Outcome {
+        let addr = match target.socket_addrs() {
+            Ok(ref addrs) if !addrs.is_empty() => addrs[0],
+            Ok(_) => return Outcome::failed(Failure::Invalid(
+                format!("No addresses for [{}]", target.url))),
+            Err(cause) => return Outcome::failed(Failure::Invalid(cause)),
+        };
+
+        let source = target.source();
+        let socket = match net::udp_socket(&addr, &source) {
+            Ok(socket) => socket,
+            Err(cause) => return Outcome::failed(Failure::Invalid(
+                format!("Failed to bind UDP socket: {}", cause))),
+        };
+        let timeout_ms = target.timeout_ms.unwrap_or(self.timeout_ms);
+        let _ = socket.set_read_timeout(
+            Some(Duration::from_millis(timeout_ms)));
+
+        // Connecting lets ICMP port unreachable errors surface on receive.
+        let result = socket.connect(addr)
+            .and_then(|_| socket.send(PAYLOAD))
+            .and_then(|_| {
+                let mut buf = [0u8; 1500];
+                socket.recv(&mut buf)
+            });
+
+        let mut outcome = match result {
+            Ok(_) => Outcome::reachable(),
+            Err(cause) => Outcome::unreachable(format!("{}: {}", addr, cause)),
+        };
+        outcome.source =
+            source.used(socket.local_addr().ok().map(|addr| addr.ip()));
+        outcome.resolved_address = Some(addr.ip());
+        outcome
+    }
+}
@@ -0,0 +1,220 @@
+use std::net::Ipv4Addr;
+
+use common::ports::parse_ports;
+
+/// Default limit on the number of targets a single range URL may expand
+/// into.
+pub const DEFAULT_MAX_EXPANSION: usize = 1024;
+
+/// A target URL whose host is an IPv4 CIDR block and/or whose port is a
+/// range, e.g: `tcp://10.0.3.0/28:8000-8010`.  Port ranges may follow any
+/// host, including bracketed IPv6 literals.
+///
+/// A numeric first path segment after an IPv4 address, as in
+/// `http://10.0.0.0/8` or `http://10.0.3.0/28/status`, is a prefix length.
+/// To probe such a path instead, give the port before it, e.g:
+/// `http://10.0.0.1:80/8`.
+struct RangeUrl<'a> {
+    scheme: &'a str,
+    host: &'a str,
+    prefix_len: Option<u32>,
+    ports: Option<&'a str>,
+    path: &'a str,
+}
+
+/// Returns whether the URL denotes a range of targets that must be
+/// expanded before probing.
+pub fn is_range_url(url: &str) -> bool {
+    match parse(url) {
+        Some(range) =>
+            range.prefix_len.is_some() ||
+            range.ports.map(|p| p.contains('-')).unwrap_or(false),
+        None => false,
+    }
+}
+
+/// Expands a range URL into one URL per address and port, failing if it
+/// would yield more than `max_expansion` URLs.  For CIDR blocks of up to
+/// /30, the network and broadcast addresses are skipped.  CIDR blocks whose
+/// address is not the network address, e.g. `10.0.0.1/8`, are rejected as
+/// ambiguous, since they are more likely a path.
+pub fn expand_url(url: &str, max_expansion: usize)
+                  -> Result<Vec<String>, String> {
+    let range = match parse(url) {
+        Some(range) => range,
+        None => return Err(format!("Invalid target URL [{}]", url)),
+    };
+
+    let (first, last) = match range.prefix_len {
+        Some(prefix_len) => {
+            let network = match range.host.parse::<Ipv4Addr>() {
+                Ok(network) if prefix_len <= 32 => u32::from(network),
+                _ => return Err(format!("Invalid CIDR block in [{}]", url)),
+            };
+            let host_bits = 32 - prefix_len;
+            let mask = if host_bits == 32 { 0 } else { !0u32 << host_bits };
+            if network & !mask != 0 {
+                return Err(format!(
+                    "Ambiguous target URL [{}]: [{}/{}] is not a CIDR block; \
+                     give the port before a numeric path",
+                    url, range.host, prefix_len));
+            }
+            let (first, last) = (network, network | !mask);
+            if host_bits >= 2 { (first + 1, last - 1) } else { (first, last) }
+        },
+        None => (0, 0),
+    };
+    let ports = match range.ports {
+        Some(ports) => try!(parse_ports(ports)
+            .map_err(|cause| format!("{} in [{}]", cause, url))),
+        None => vec!(),
+    };
+
+    let host_count = (last - first) as u64 + 1;
+    let port_count = if ports.is_empty() { 1 } else { ports.len() as u64 };
+    if host_count * port_count > max_expansion as u64 {
+        return Err(format!(
+            "[{}] expands to {} targets, more than the limit of {}",
+            url, host_count * port_count, max_expansion));
+    }
+
+    let hosts: Vec<String> = match range.prefix_len {
+        Some(_) => (first..last).chain(Some(last))
+            .map(|ip| format!("{}", Ipv4Addr::from(ip)))
+            .collect(),
+        None => vec!(range.host.to_string()),
+    };
+    let mut urls = vec!();
+    for host in hosts.iter() {
+        if ports.is_empty() {
+            urls.push(format!("{}://{}{}", range.scheme, host, range.path));
+        }
+        for port in ports.iter() {
+            urls.push(format!("{}://{}:{}{}",
+                              range.scheme, host, port, range.path));
+        }
+    }
+    Ok(urls)
+}
+
+fn parse(url: &str) -> Option<RangeUrl> {
+    let scheme_end = match url.find("://") {
+        Some(index) => index,
+        None => return None,
+    };
+    let scheme = &url[..scheme_end];
+    let rest = &url[scheme_end + 3..];
+
+    // IPv6 literals are bracketed and keep their brackets, e.g:
+    // `tcp://[fd00::1]:8000-8010`.
+    let host_end = if rest.starts_with('[') {
+        match rest.find(']') {
+            Some(end) => end + 1,
+            None => return None,
+        }
+    } else {
+        rest.find(|c| c == ':' || c == '/' || c == '?').unwrap_or(rest.len())
+    };
+    let host = &rest[..host_end];
+    let mut rest = &rest[host_end..];
+
+    // A CIDR prefix length follows an IPv4 address and ends the authority
+    // unless a port follows it.
+    let mut prefix_len = None;
+    if rest.starts_with("/") && host.parse::<Ipv4Addr>().is_ok() {
+        let end = rest[1..].find(|c| c == ':' || c == '/' || c == '?')
+            .map(|index| index + 1)
+            .unwrap_or(rest.len());
+        if let Ok(len) = rest[1..end].parse::<u32>() {
+            prefix_len = Some(len);
+            rest = &rest[end..];
+        }
+    }
+
+    let mut ports = None;
+    if rest.starts_with(":") {
+        let end = rest.find(|c| c == '/' || c == '?').unwrap_or(rest.len());
+        ports = Some(&rest[1..end]);
+        rest = &rest[end..];
+    }
+
+    Some(RangeUrl {
+        scheme: scheme,
+        host: host,
+        prefix_len: prefix_len,
+        ports: ports,
+        path: rest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_url, is_range_url};
+
+    fn expand(url: &str) -> Result<Vec<String>, String> {
+        expand_url(url, 100)
+    }
+
+    #[test]
+    fn expand_cidr_blocks() {
+        assert_eq!(expand("tcp://10.0.3.0/30:80").unwrap(),
+                   vec!("tcp://10.0.3.1:80", "tcp://10.0.3.2:80"));
+        assert_eq!(expand("tcp://10.0.3.0/31:80").unwrap(),
+                   vec!("tcp://10.0.3.0:80", "tcp://10.0.3.1:80"));
+        assert_eq!(expand("icmp://10.0.3.7/32").unwrap(),
+                   vec!("icmp://10.0.3.7"));
+        assert!(expand("icmp://0.0.0.0/0").unwrap_err()
+                .contains("more than the limit of 100"));
+        assert!(expand("icmp://10.0.3.0/33").is_err());
+    }
+
+    #[test]
+    fn expand_cidr_blocks_with_paths() {
+        assert_eq!(expand("http://10.0.3.0/30/status").unwrap(),
+                   vec!("http://10.0.3.1/status", "http://10.0.3.2/status"));
+        assert_eq!(expand("http://10.0.3.0/30:9000-9001/status").unwrap(),
+                   vec!("http://10.0.3.1:9000/status",
+                        "http://10.0.3.1:9001/status",
+                        "http://10.0.3.2:9000/status",
+                        "http://10.0.3.2:9001/status"));
+        assert_eq!(expand("http://10.0.3.0/30?verbose").unwrap(),
+                   vec!("http://10.0.3.1?verbose", "http://10.0.3.2?verbose"));
+    }
+
+    #[test]
+    fn reject_ambiguous_paths() {
+        assert!(is_range_url("http://10.0.0.1/8"));
+        assert!(expand("http://10.0.0.1/8").unwrap_err()
+                .contains("Ambiguous"));
+        assert!(!is_range_url("http://10.0.0.1:80/8"));
+        assert!(!is_range_url("http://10.0.0.1/8x"));
+        assert!(!is_range_url("http://10.0.0.1/status"));
+        assert!(!is_range_url("http://10.0.0.1/"));
+    }
+
+    #[test]
+    fn expand_port_ranges() {
+        assert_eq!(expand("tcp://[fd00::1]:8000-8002").unwrap(),
+                   vec!("tcp://[fd00::1]:8000",
+                        "tcp://[fd00::1]:8001",
+                        "tcp://[fd00::1]:8002"));
+        assert_eq!(expand("http://[fd00::1]:9000-9001/status").unwrap(),
+                   vec!("http://[fd00::1]:9000/status",
+                        "http://[fd00::1]:9001/status"));
+        assert!(is_range_url("udp://host.example.com:53-54"));
+        assert!(!is_range_url("tcp://[fd00::1]:8000"));
+        assert!(expand("tcp://[fd00::1]:8002-8000").is_err());
+    }
+
+    #[test]
+    fn enforce_the_expansion_limit() {
+        assert_eq!(expand_url("tcp://10.0.3.0/28:8000-8009", 140)
+                   .unwrap().len(),
+                   140);
+        let error = expand_url("tcp://10.0.3.0/28:8000-8010", 140)
+            .unwrap_err();
+        assert_eq!(error,
+                   "[tcp://10.0.3.0/28:8000-8010] expands to 154 targets, \
+                    more than the limit of 140");
+    }
+}
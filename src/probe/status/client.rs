@@ -1,74 +1,685 @@
-use std::sync::{Arc, RwLock};
-use std::thread;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-use probe::status::StatusCache;
+use common::dns::{self, Resolver};
+use common::net::{Family, Source};
+use probe::heartbeat;
+use probe::protocol::{self, Failure, Outcome, Registry};
+use probe::range;
+use probe::status::{self, FamilyStatus, Reachability, StatusCache};
+use probe::target::TargetSpec;
 
-use hyper::client::Response;
-use hyper::Client;
-use hyper::error::Error;
-use hyper::header::Connection;
-use mio::{EventLoop, Handler};
+use mio::{EventLoop, Handler, Sender};
 use threadpool::ThreadPool;
 
-pub fn start_client_driver(targets: Vec<String>,
-                          http_probe_ms: u64,
-                          status_cache: Arc<RwLock<StatusCache>>) {
-    info!("Starting client driver");
-    let mut event_loop = EventLoop::new().unwrap();
-    let _ = event_loop.timeout_ms((), http_probe_ms);
-    thread::spawn(move || {
-        let _ = event_loop.run(&mut ClientHandler {
-            targets: targets,
-            http_probe_ms: http_probe_ms,
+const DEFAULT_PROBE_MS: u64 = 5000;
+const DEFAULT_THREADS: usize = 4;
+
+/// Schemes whose probers resolve the host of the target themselves and
+/// honor its family.
+const FAMILY_SCHEMES: &'static [&'static str] =
+    &["http", "https", "icmp", "mtu", "star", "tcp", "udp"];
+
+/// The result of a round of probes of a target.
+#[derive(Clone, Debug)]
+pub struct ProbeResult {
+    pub url: String,
+    /// The outcome of the last attempt.
+    pub outcome: Outcome,
+    /// The outcomes of every attempt, in order.
+    pub outcomes: Vec<Outcome>,
+    /// For hostname targets, the outcomes of the attempts to the addresses
+    /// of each family.  An attempt in `outcomes` succeeds if it succeeded
+    /// for any family.
+    pub families: BTreeMap<Family, Vec<Outcome>>,
+}
+
+impl ProbeResult {
+    /// Whether any attempt succeeded.
+    pub fn is_reachable(&self) -> bool {
+        self.reachability() != Reachability::Unreachable
+    }
+
+    pub fn reachability(&self) -> Reachability {
+        Reachability::of(&self.outcomes)
+    }
+
+    pub fn loss_pct(&self) -> f64 {
+        status::loss_pct(&self.outcomes)
+    }
+}
+
+pub type ResultCallback = Box<Fn(&ProbeResult) + Send + Sync>;
+
+/// Builds a probe engine, which periodically probes a set of targets and
+/// records their reachability in a `StatusCache`.
+///
+/// ```no_run
+/// use star::probe::status::client::ProberBuilder;
+///
+/// let mut prober = ProberBuilder::new()
+///     .target("http://127.0.0.1:9000/status")
+///     .interval_ms(1000);
+/// let results = prober.results();
+/// let mut handle = prober.build();
+///
+/// handle.start();
+/// let result = results.recv().unwrap();
+/// println!("{} reachable: {}", result.url, result.is_reachable());
+/// handle.stop();
+/// ```
+///
+/// Targets declared with a DNS discovery URL (see `common::dns`) are
+/// re-resolved every interval, and each discovered endpoint is tracked as a
+/// separate target.  Likewise, targets declared with a range URL (see
+/// `probe::range`) are expanded into one target per address and port.
+///
+/// Targets are probed by the `protocol::Prober` registered for their URL
+/// scheme; by default those built into star.  Each round may make several
+/// attempts per target, so that packet loss shows as a degraded target
+/// rather than an outage.
+pub struct ProberBuilder {
+    targets: Vec<TargetSpec>,
+    interval_ms: u64,
+    attempts: u32,
+    attempt_interval_ms: u64,
+    threads: usize,
+    registry: Registry,
+    resolver: Option<Resolver>,
+    max_expansion: usize,
+    source: Source,
+    nameserver: Option<SocketAddr>,
+    status_cache: Option<Arc<RwLock<StatusCache>>>,
+    callbacks: Vec<ResultCallback>,
+}
+
+impl ProberBuilder {
+    pub fn new() -> ProberBuilder {
+        ProberBuilder {
+            targets: vec!(),
+            interval_ms: DEFAULT_PROBE_MS,
+            attempts: 1,
+            attempt_interval_ms: 0,
+            threads: DEFAULT_THREADS,
+            registry: Registry::with_defaults(),
+            resolver: None,
+            max_expansion: range::DEFAULT_MAX_EXPANSION,
+            source: Source::default(),
+            nameserver: None,
+            status_cache: None,
+            callbacks: vec!(),
+        }
+    }
+
+    pub fn target(mut self, url: &str) -> ProberBuilder {
+        self.targets.push(TargetSpec::new(url));
+        self
+    }
+
+    pub fn targets(mut self, urls: Vec<String>) -> ProberBuilder {
+        self.targets.extend(urls.iter().map(|url| TargetSpec::new(url)));
+        self
+    }
+
+    /// Adds a target with prober-specific settings.  Targets whose settings
+    /// do not validate are reported as invalid instead of being probed.
+    pub fn target_spec(mut self, target: TargetSpec) -> ProberBuilder {
+        self.targets.push(target);
+        self
+    }
+
+    /// Adds targets with prober-specific settings, like `target_spec`.
+    pub fn target_specs(mut self, targets: Vec<TargetSpec>) -> ProberBuilder {
+        self.targets.extend(targets);
+        self
+    }
+
+    /// Milliseconds between probes of each target.
+    pub fn interval_ms(mut self, interval_ms: u64) -> ProberBuilder {
+        self.interval_ms = interval_ms;
+        self
+    }
+
+    /// Number of attempts to probe each target per round.  The attempts are
+    /// made one after another, so a round takes up to `attempts` times the
+    /// probe timeout.
+    pub fn attempts(mut self, attempts: u32) -> ProberBuilder {
+        self.attempts = if attempts == 0 { 1 } else { attempts };
+        self
+    }
+
+    /// Milliseconds to wait between the attempts of a round.
+    pub fn attempt_interval_ms(mut self, attempt_interval_ms: u64)
+                               -> ProberBuilder {
+        self.attempt_interval_ms = attempt_interval_ms;
+        self
+    }
+
+    /// Number of threads used to probe targets concurrently.
+    pub fn threads(mut self, threads: usize) -> ProberBuilder {
+        self.threads = threads;
+        self
+    }
+
+    /// Probes targets with the probers of the supplied registry instead of
+    /// the default ones.
+    pub fn registry(mut self, registry: Registry) -> ProberBuilder {
+        self.registry = registry;
+        self
+    }
+
+    /// Probes targets with the supplied URL scheme using the prober.
+    pub fn register(mut self, scheme: &str, prober: Arc<protocol::Prober>)
+                    -> ProberBuilder {
+        self.registry.register(scheme, prober);
+        self
+    }
+
+    /// Resolver used to expand DNS discovery URLs.
+    pub fn resolver(mut self, resolver: Resolver) -> ProberBuilder {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Limits the number of targets a single range URL may expand into.
+    /// Range URLs that exceed it are reported as invalid.
+    pub fn max_expansion(mut self, max_expansion: usize) -> ProberBuilder {
+        self.max_expansion = max_expansion;
+        self
+    }
+
+    /// Probes from the source, e.g. a local address or network device of a
+    /// multi-homed host, unless a target has a source of its own.
+    pub fn source(mut self, source: Source) -> ProberBuilder {
+        self.source = source;
+        self
+    }
+
+    /// Resolves the hosts of targets with the nameserver instead of the
+    /// system resolver, unless a target has a nameserver of its own.  This
+    /// does not change the resolver used to expand DNS discovery URLs.
+    pub fn nameserver(mut self, nameserver: SocketAddr) -> ProberBuilder {
+        self.nameserver = Some(nameserver);
+        self
+    }
+
+    /// Records results in the supplied status cache, e.g. one that is also
+    /// served over HTTP, instead of a new one.
+    pub fn status_cache(mut self, status_cache: Arc<RwLock<StatusCache>>)
+                        -> ProberBuilder {
+        self.status_cache = Some(status_cache);
+        self
+    }
+
+    /// Invokes the callback with the result of every probe.  Callbacks are
+    /// invoked on the probe threads.
+    pub fn on_result<F>(mut self, callback: F) -> ProberBuilder
+        where F: Fn(&ProbeResult) + Send + Sync + 'static {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Returns a channel that receives the result of every probe.
+    pub fn results(&mut self) -> Receiver<ProbeResult> {
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        self.callbacks.push(Box::new(move |result: &ProbeResult| {
+            let _ = sender.lock().unwrap().send(result.clone());
+        }));
+        receiver
+    }
+
+    pub fn build(self) -> ProberHandle {
+        let status_cache = self.status_cache.unwrap_or_else(||
+            Arc::new(RwLock::new(StatusCache::new(&vec!()))));
+        status_cache.write().unwrap().sync_targets(
+            &self.targets.iter()
+                .filter(|t| !is_expanded(&t.url))
+                .map(|t| t.url.clone())
+                .collect());
+
+        ProberHandle {
+            targets: Arc::new(RwLock::new(self.targets)),
+            interval_ms: self.interval_ms,
+            attempts: self.attempts,
+            attempt_interval_ms: self.attempt_interval_ms,
+            threads: self.threads,
+            registry: Arc::new(self.registry),
+            resolver: self.resolver,
+            max_expansion: self.max_expansion,
+            source: self.source,
+            nameserver: self.nameserver,
             status_cache: status_cache,
-            thread_pool: ThreadPool::new(4),
+            callbacks: Arc::new(self.callbacks),
+            running: None,
+        }
+    }
+}
+
+enum Command {
+    Probe(TargetSpec),
+    Shutdown,
+}
+
+/// Controls a probe engine built by a `ProberBuilder`.  The engine is stopped
+/// when the handle is dropped.
+pub struct ProberHandle {
+    targets: Arc<RwLock<Vec<TargetSpec>>>,
+    interval_ms: u64,
+    attempts: u32,
+    attempt_interval_ms: u64,
+    threads: usize,
+    registry: Arc<Registry>,
+    resolver: Option<Resolver>,
+    max_expansion: usize,
+    source: Source,
+    nameserver: Option<SocketAddr>,
+    status_cache: Arc<RwLock<StatusCache>>,
+    callbacks: Arc<Vec<ResultCallback>>,
+    running: Option<(Sender<Command>, JoinHandle<()>)>,
+}
+
+impl ProberHandle {
+    /// Starts probing on a new thread, beginning with an immediate round of
+    /// probes.  Does nothing if the engine is already running.
+    pub fn start(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+        info!("Starting client driver");
+
+        let mut event_loop = EventLoop::new().unwrap();
+        let _ = event_loop.timeout_ms((), 0);
+        let channel = event_loop.channel();
+
+        let mut handler = ClientHandler {
+            targets: self.targets.clone(),
+            discovered: HashMap::new(),
+            heartbeats: HashMap::new(),
+            resolver: self.resolver.clone(),
+            max_expansion: self.max_expansion,
+            source: self.source.clone(),
+            nameserver: self.nameserver,
+            http_probe_ms: self.interval_ms,
+            attempts: self.attempts,
+            attempt_interval_ms: self.attempt_interval_ms,
+            registry: self.registry.clone(),
+            status_cache: self.status_cache.clone(),
+            callbacks: self.callbacks.clone(),
+            thread_pool: ThreadPool::new(self.threads),
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+        };
+        let thread = thread::spawn(move || {
+            let _ = event_loop.run(&mut handler);
         });
-    });
+        self.running = Some((channel, thread));
+    }
+
+    /// Stops probing and waits for the engine thread to exit.  Probes that
+    /// are already in flight may still complete.
+    pub fn stop(&mut self) {
+        if let Some((channel, thread)) = self.running.take() {
+            info!("Stopping client driver");
+            let _ = channel.send(Command::Shutdown);
+            let _ = thread.join();
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Adds a target, probing it immediately if the engine is running.
+    pub fn add_target(&self, url: &str) -> Result<(), String> {
+        self.add_target_spec(TargetSpec::new(url))
+    }
+
+    /// Adds a target with prober-specific settings, replacing any existing
+    /// target with the same URL.  Fails without adding the target if its
+    /// settings do not validate.
+    pub fn add_target_spec(&self, target: TargetSpec) -> Result<(), String> {
+        try!(target.validate());
+        {
+            let mut targets = self.targets.write().unwrap();
+            targets.retain(|t| t.url != target.url);
+            targets.push(target.clone());
+        }
+        if is_expanded(&target.url) {
+            return Ok(());
+        }
+        self.status_cache.write().unwrap().add_target(target.url.clone());
+        if let Some((ref channel, _)) = self.running {
+            let _ = channel.send(Command::Probe(target));
+        }
+        Ok(())
+    }
+
+    /// Removes a target.  The targets discovered from a DNS discovery URL or
+    /// expanded from a range URL are removed at the start of the next round
+    /// of probes.
+    pub fn remove_target(&self, url: &str) {
+        self.targets.write().unwrap().retain(|t| t.url != url);
+        self.status_cache.write().unwrap().remove_target(url);
+    }
+
+    pub fn targets(&self) -> Vec<TargetSpec> {
+        self.targets.read().unwrap().clone()
+    }
+
+    pub fn status_cache(&self) -> Arc<RwLock<StatusCache>> {
+        self.status_cache.clone()
+    }
+}
+
+impl Drop for ProberHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
 }
 
 struct ClientHandler {
-    targets: Vec<String>,
+    targets: Arc<RwLock<Vec<TargetSpec>>>,
+    discovered: HashMap<String, Vec<TargetSpec>>,
+    heartbeats: HashMap<String, Arc<AtomicBool>>,
+    resolver: Option<Resolver>,
+    max_expansion: usize,
+    source: Source,
+    nameserver: Option<SocketAddr>,
     http_probe_ms: u64,
+    attempts: u32,
+    attempt_interval_ms: u64,
+    registry: Arc<Registry>,
     status_cache: Arc<RwLock<StatusCache>>,
+    callbacks: Arc<Vec<ResultCallback>>,
     thread_pool: ThreadPool,
+    /// URLs of the targets whose round of probes is queued or running.
+    in_flight: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ClientHandler {
+    /// Expands the declared targets into concrete targets and updates the
+    /// status cache to track exactly those.  If a discovery URL cannot be
+    /// resolved, its previously discovered targets are kept.  Range URLs
+    /// that cannot be expanded and targets whose settings do not validate
+    /// are recorded as invalid targets.  DNS
+    /// discovery URLs are resolved with their own nameserver if they have
+    /// one.  Concrete targets get the default source and nameserver unless
+    /// they have their own.
+    fn discover(&mut self) -> Vec<TargetSpec> {
+        let targets = self.targets.read().unwrap().clone();
+        self.discovered.retain(|url, _| targets.iter().any(|t| &t.url == url));
+
+        let mut concrete = vec!();
+        let mut ranges = BTreeMap::new();
+        let mut invalid = vec!();
+        for target in targets.iter() {
+            if let Err(cause) = target.validate() {
+                warn!("Invalid target [{}]: {}", target.url, cause);
+                invalid.push((target.url.clone(), cause));
+                continue;
+            }
+            if range::is_range_url(&target.url) {
+                match range::expand_url(&target.url, self.max_expansion) {
+                    Ok(urls) => {
+                        concrete.extend(urls.iter()
+                            .map(|url| target.with_url(url.clone())));
+                        ranges.insert(target.url.clone(), urls);
+                    },
+                    Err(cause) => {
+                        warn!("Failed to expand target [{}]: {}",
+                              target.url,
+                              cause);
+                        invalid.push((target.url.clone(), cause));
+                    },
+                }
+                continue;
+            }
+            if !dns::is_discovery_url(&target.url) {
+                concrete.push(target.clone());
+                continue;
+            }
+
+            let resolver = match target.nameserver() {
+                Ok(Some(nameserver)) => Ok(Resolver::new(nameserver)),
+                Ok(None) => self.resolver.clone()
+                    .ok_or("No DNS resolver is configured".to_string()),
+                Err(cause) => Err(cause),
+            };
+            let expanded = resolver.and_then(|resolver|
+                dns::expand_url(&target.url, &resolver));
+            match expanded {
+                Ok(discovered) => {
+                    let specs = discovered.into_iter()
+                        .map(|d| target.with_url(d.url))
+                        .collect();
+                    self.discovered.insert(target.url.clone(), specs);
+                },
+                Err(cause) => warn!("Failed to resolve target [{}]: {}",
+                                    target.url,
+                                    cause),
+            }
+            if let Some(specs) = self.discovered.get(&target.url) {
+                concrete.extend(specs.iter().cloned());
+            }
+        }
+
+        let mut target_urls: Vec<String> =
+            concrete.iter().map(|t| t.url.clone()).collect();
+        target_urls.extend(invalid.iter().map(|&(ref url, _)| url.clone()));
+        let concrete: Vec<TargetSpec> =
+            concrete.iter().map(|t| self.with_defaults(t)).collect();
+
+        let mut status_cache = self.status_cache.write().unwrap();
+        status_cache.sync_targets(&target_urls);
+        status_cache.sync_ranges(ranges);
+        status_cache.sync_specs(&concrete);
+        for (url, cause) in invalid {
+            status_cache.record(url, &Outcome::failed(Failure::Invalid(cause)));
+        }
+        concrete
+    }
+
+    /// The target with the default source and nameserver, unless it has
+    /// its own.
+    fn with_defaults(&self, target: &TargetSpec) -> TargetSpec {
+        let target = target.with_default_source(&self.source);
+        match self.nameserver {
+            Some(ref nameserver) => target.with_default_dns_server(nameserver),
+            None => target,
+        }
+    }
+
+    /// Starts sending heartbeats to the concrete targets that ask for them
+    /// and stops sending them to the rest.
+    fn sync_heartbeats(&mut self, targets: &Vec<TargetSpec>) {
+        let wanted: HashMap<String, TargetSpec> = targets.iter()
+            .filter(|t| t.heartbeat.is_some())
+            .map(|t| (t.url.clone(), t.clone()))
+            .collect();
+
+        for (url, stop) in self.heartbeats.iter() {
+            if !wanted.contains_key(url) {
+                stop.store(true, Ordering::SeqCst);
+            }
+        }
+        self.heartbeats.retain(|url, _| wanted.contains_key(url));
+
+        for (url, target) in wanted {
+            if !self.heartbeats.contains_key(&url) {
+                let stop = Arc::new(AtomicBool::new(false));
+                heartbeat::start_heartbeat(target,
+                                           self.status_cache.clone(),
+                                           stop.clone());
+                self.heartbeats.insert(url, stop);
+            }
+        }
+    }
+
+    /// Queues a round of probes of the target, unless its previous round
+    /// is still queued or running, e.g. because its attempts take longer
+    /// than the interval.
+    fn probe(&self, target: TargetSpec) {
+        let in_flight = match InFlight::start(&self.in_flight, &target.url) {
+            Some(in_flight) => in_flight,
+            None => {
+                warn!("Skipping target [{}]: its previous round of probes \
+                       is still in flight",
+                      target.url);
+                return;
+            },
+        };
+        let registry = self.registry.clone();
+        let status_cache = self.status_cache.clone();
+        let callbacks = self.callbacks.clone();
+        let attempts = self.attempts;
+        let attempt_interval_ms = self.attempt_interval_ms;
+        self.thread_pool.execute(move || {
+            let _in_flight = in_flight;
+            info!("Probing target: [{}]", target.url);
+
+            // Each attempt probes the addresses of every family of a
+            // hostname separately, and succeeds if any of them is reachable.
+            let families = families_of(&target);
+            let mut outcomes = vec!();
+            let mut family_outcomes = BTreeMap::new();
+            for attempt in 0..attempts {
+                if attempt > 0 && attempt_interval_ms > 0 {
+                    thread::sleep(Duration::from_millis(attempt_interval_ms));
+                }
+                if families.is_empty() {
+                    outcomes.push(registry.probe(&target));
+                    continue;
+                }
+                let mut attempt_outcome: Option<Outcome> = None;
+                for family in families.iter() {
+                    let outcome = registry.probe(&target.with_family(*family));
+                    family_outcomes.entry(*family)
+                        .or_insert(vec!())
+                        .push(outcome.clone());
+                    let reachable = attempt_outcome.as_ref()
+                        .map_or(false, |o| o.is_reachable());
+                    if !reachable {
+                        attempt_outcome = Some(outcome);
+                    }
+                }
+                outcomes.extend(attempt_outcome);
+            }
+            let result = match outcomes.last().cloned() {
+                Some(outcome) => ProbeResult {
+                    url: target.url,
+                    outcome: outcome,
+                    outcomes: outcomes,
+                    families: family_outcomes,
+                },
+                None => return,
+            };
+
+            {
+                // Obtain an exclusive write lock to the status cache.
+                let mut status_cache = status_cache.write().unwrap();
+                status_cache.record_round(result.url.clone(), &result.outcomes);
+                status_cache.record_families(
+                    &result.url,
+                    result.families.iter()
+                        .map(|(family, outcomes)|
+                             FamilyStatus::of(*family, outcomes))
+                        .collect());
+            }
+
+            for callback in callbacks.iter() {
+                callback(&result);
+            }
+        });
+    }
 }
 
 impl Handler for ClientHandler {
     type Timeout = ();
-    type Message = String;
+    type Message = Command;
 
     fn timeout(&mut self,
                event_loop: &mut EventLoop<ClientHandler>,
                _: ()) {
         info!("Probing all targets");
-        let loop_channel = event_loop.channel();
-        for target in self.targets.clone() {
-            let _ = loop_channel.send(target);
+        let targets = self.discover();
+        self.sync_heartbeats(&targets);
+        for target in targets {
+            self.probe(target);
         }
         let _ = event_loop.timeout_ms((), self.http_probe_ms);
     }
 
     fn notify(&mut self,
-              _: &mut EventLoop<ClientHandler>,
-              target_url: String) {
-        let status_cache = self.status_cache.clone();
-        self.thread_pool.execute(move || {
-            info!("Probing target: [{}]", target_url);
+              event_loop: &mut EventLoop<ClientHandler>,
+              command: Command) {
+        match command {
+            Command::Probe(target) => self.probe(self.with_defaults(&target)),
+            Command::Shutdown => {
+                self.sync_heartbeats(&vec!());
+                event_loop.shutdown();
+            },
+        }
+    }
+}
 
-            let client = Client::new();
+/// Marks the round of probes of a target as in flight until dropped, even if
+/// a prober panics.
+struct InFlight {
+    urls: Arc<Mutex<HashSet<String>>>,
+    url: String,
+}
 
-            let response: Result<Response, Error> =
-                client.get(&target_url)
-                    .header(Connection::close())
-                    .send();
+impl InFlight {
+    /// Marks the target as in flight, unless it already is.
+    fn start(urls: &Arc<Mutex<HashSet<String>>>, url: &str)
+             -> Option<InFlight> {
+        if !urls.lock().unwrap().insert(url.to_string()) {
+            return None;
+        }
+        Some(InFlight { urls: urls.clone(), url: url.to_string() })
+    }
+}
 
-            // Obtain an exclusive write lock to the status cache.
-            let mut status_cache = status_cache.write().unwrap();
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.urls.lock().unwrap().remove(&self.url);
+    }
+}
 
-            match response {
-                Ok(_) => status_cache.reachable(target_url),
-                Err(_) => status_cache.unreachable(target_url),
-            }
-        });
+/// Whether the URL is expanded into other targets rather than probed.
+fn is_expanded(url: &str) -> bool {
+    dns::is_discovery_url(url) || range::is_range_url(url)
+}
+
+/// The address families to probe separately: those the hostname of the
+/// target resolves to, except any the source address of the target cannot
+/// reach.  Targets whose host is an IP address, which are limited to a
+/// family or whose prober does not resolve hosts are probed as a whole.
+fn families_of(target: &TargetSpec) -> Vec<Family> {
+    let supported = target.scheme()
+        .map(|scheme| FAMILY_SCHEMES.contains(&scheme))
+        .unwrap_or(false);
+    if target.family.is_some() || !supported {
+        return vec!();
+    }
+    match target.host() {
+        Ok(ref host) if host.parse::<IpAddr>().is_err() => (),
+        _ => return vec!(),
     }
+    let source_family = target.source().family();
+    let mut families: Vec<Family> = match target.ip_addrs() {
+        Ok(ips) => ips.iter()
+            .map(Family::of)
+            .filter(|family| source_family.map_or(true, |f| f == *family))
+            .collect(),
+        Err(_) => return vec!(),
+    };
+    families.sort();
+    families.dedup();
+    families
 }
@@ -1,55 +1,340 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+use common::net::{Family, Source};
+use probe::heartbeat::Flow;
+use probe::protocol::{Failure, Outcome, Responder, Timing};
+use probe::target::TargetSpec;
+use probe::throughput::Throughput;
 
 pub mod client;
 
 pub struct Status {
     pub targets: Vec<Target>,
+    /// Summaries of the targets expanded from each range URL.
+    pub ranges: Vec<RangeSummary>,
+}
+
+/// Reachability of the targets expanded from a range URL, e.g:
+/// `tcp://10.0.3.0/28:8000-8010`.
+#[derive(Clone)]
+pub struct RangeSummary {
+    pub url: String,
+    pub target_urls: Vec<String>,
+    pub reachable: usize,
+    pub degraded: usize,
+    pub unreachable: usize,
 }
 
+/// How reliably a target answered the attempts of a round of probes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reachability {
+    /// Every attempt succeeded.
+    Reachable,
+    /// Some, but not all, attempts succeeded.
+    Degraded,
+    /// No attempt succeeded.
+    Unreachable,
+}
+
+impl Reachability {
+    pub fn of(outcomes: &[Outcome]) -> Reachability {
+        let successes = outcomes.iter().filter(|o| o.is_reachable()).count();
+        if successes == 0 {
+            Reachability::Unreachable
+        } else if successes < outcomes.len() {
+            Reachability::Degraded
+        } else {
+            Reachability::Reachable
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Reachability::Reachable => "reachable",
+            Reachability::Degraded => "degraded",
+            Reachability::Unreachable => "unreachable",
+        }
+    }
+}
+
+/// Mean round trip time of the successful attempts.
+fn mean_rtt_ms(outcomes: &[Outcome]) -> Option<f64> {
+    let rtts: Vec<f64> = outcomes.iter().filter_map(|o| o.rtt_ms).collect();
+    if rtts.is_empty() {
+        None
+    } else {
+        let total = rtts.iter().fold(0.0, |sum, rtt| sum + rtt);
+        Some(total / rtts.len() as f64)
+    }
+}
+
+/// The failure of the last failed attempt.
+fn last_failure(outcomes: &[Outcome]) -> Option<Failure> {
+    outcomes.iter().rev().filter_map(|o| o.failure.clone()).next()
+}
+
+/// Percentage of the attempts that failed.
+pub fn loss_pct(outcomes: &[Outcome]) -> f64 {
+    if outcomes.is_empty() {
+        return 0.0;
+    }
+    let failures = outcomes.iter().filter(|o| !o.is_reachable()).count();
+    100.0 * failures as f64 / outcomes.len() as f64
+}
+
+/// Reachability of the addresses of one family of a hostname target.
+#[derive(Clone)]
+pub struct FamilyStatus {
+    pub family: Family,
+    pub reachability: Reachability,
+    pub loss_pct: f64,
+    pub rtt_ms: Option<f64>,
+    /// Why the last failed attempt of the most recent round failed.
+    pub failure: Option<Failure>,
+}
+
+impl FamilyStatus {
+    pub fn of(family: Family, outcomes: &[Outcome]) -> FamilyStatus {
+        FamilyStatus {
+            family: family,
+            reachability: Reachability::of(outcomes),
+            loss_pct: loss_pct(outcomes),
+            rtt_ms: mean_rtt_ms(outcomes),
+            failure: last_failure(outcomes),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Target {
     pub url: String,
+    /// Whether any attempt of the most recent round succeeded.
     pub reachable: bool,
+    pub reachability: Reachability,
+    /// Percentage of the attempts of the most recent round that failed.
+    pub loss_pct: f64,
+    /// Why the most recent probe failed, if it did.
+    pub failure: Option<Failure>,
+    /// Mean round trip time of the successful attempts of the most recent
+    /// round.
+    pub rtt_ms: Option<f64>,
+    /// Source address of the most recent probe as observed by the target.
+    pub observed_address: Option<String>,
+    /// The local source the most recent probe left from, if one was
+    /// configured.
+    pub source: Option<Source>,
+    /// The address the host of the target resolved to in the most recent
+    /// probe that resolved it.
+    pub resolved_address: Option<IpAddr>,
+    /// Phases of the most recent attempt that measured them.
+    pub timing: Option<Timing>,
+    /// The path MTU found by the most recent attempt that measured it.
+    pub path_mtu: Option<u32>,
+    /// The star process that answered the most recent `star://` probe.
+    pub responder: Option<Responder>,
+    /// The long-lived connection to the target, if it is sent heartbeats.
+    /// This is independent of the reachability of new connections.
+    pub heartbeat: Option<Flow>,
+    /// The most recent throughput test in each direction, if any were run.
+    pub throughput: Vec<Throughput>,
+    /// For hostname targets, the reachability of the addresses of each
+    /// family the name resolved to in the most recent round.
+    pub families: Vec<FamilyStatus>,
+}
+
+impl Target {
+    /// A target that has not been probed yet.
+    pub fn new(url: String) -> Target {
+        Target {
+            url: url,
+            reachable: false,
+            reachability: Reachability::Unreachable,
+            loss_pct: 0.0,
+            failure: None,
+            rtt_ms: None,
+            observed_address: None,
+            source: None,
+            resolved_address: None,
+            timing: None,
+            path_mtu: None,
+            responder: None,
+            heartbeat: None,
+            throughput: vec!(),
+            families: vec!(),
+        }
+    }
 }
 
 pub struct StatusCache {
-    state: HashMap<String, bool>,
+    state: HashMap<String, Target>,
+    ranges: BTreeMap<String, Vec<String>>,
+    /// The settings each concrete target is probed with, by URL.
+    specs: HashMap<String, TargetSpec>,
 }
 
 impl StatusCache {
     pub fn new(target_urls: &Vec<String>) -> StatusCache {
         let mut initial_state = HashMap::new();
         for target in target_urls {
-            initial_state.insert(target.clone(), false);
+            initial_state.insert(target.clone(), Target::new(target.clone()));
+        }
+        StatusCache {
+            state: initial_state,
+            ranges: BTreeMap::new(),
+            specs: HashMap::new(),
         }
-        StatusCache { state: initial_state, }
     }
 
     pub fn poll(&self) -> Status {
-        let targets = self.state.iter().map(|(url, reachable)|
-                        Target {
-                            url: url.clone(),
-                            reachable: reachable.clone(),
-                        }
-                    ).collect();
-        Status { targets: targets, }
+        let targets = self.state.values().cloned().collect();
+        let ranges = self.ranges.iter().map(|(url, target_urls)| {
+            let count = |reachability: Reachability| target_urls.iter()
+                .filter(|u| self.state.get(*u)
+                        .map(|t| t.reachability == reachability)
+                        .unwrap_or(false))
+                .count();
+            let reachable = count(Reachability::Reachable);
+            let degraded = count(Reachability::Degraded);
+            RangeSummary {
+                url: url.clone(),
+                target_urls: target_urls.clone(),
+                reachable: reachable,
+                degraded: degraded,
+                unreachable: target_urls.len() - reachable - degraded,
+            }
+        }).collect();
+        Status { targets: targets, ranges: ranges, }
+    }
+
+    /// Replaces the set of range URLs, each with the URLs of the targets it
+    /// expands into.
+    pub fn sync_ranges(&mut self, ranges: BTreeMap<String, Vec<String>>) {
+        self.ranges = ranges;
+    }
+
+    /// Replaces the set of targets, keeping the state of targets that
+    /// remain and treating new targets as unreachable.
+    pub fn sync_targets(&mut self, target_urls: &Vec<String>) {
+        self.state.retain(|url, _| target_urls.contains(url));
+        for target in target_urls {
+            if !self.state.contains_key(target) {
+                info!("Tracking new target [{}]", target);
+                self.state.insert(target.clone(), Target::new(target.clone()));
+            }
+        }
+    }
+
+    /// Replaces the settings the concrete targets are probed with.
+    pub fn sync_specs(&mut self, specs: &[TargetSpec]) {
+        self.specs = specs.iter()
+            .map(|spec| (spec.url.clone(), spec.clone()))
+            .collect();
+    }
+
+    /// The settings the target with the URL is probed with, e.g. for
+    /// on-demand tests that must leave from the same source.
+    pub fn spec(&self, target_url: &str) -> Option<TargetSpec> {
+        self.specs.get(target_url).cloned()
+    }
+
+    pub fn contains(&self, target_url: &str) -> bool {
+        self.state.contains_key(target_url)
     }
 
-    fn update(&mut self, target_url: String, reachable: bool) {
+    /// Starts tracking the target, initially as unreachable.
+    pub fn add_target(&mut self, target_url: String) {
         if !self.state.contains_key(&target_url) {
-            warn!("Received update state for unknown target [{}]",
-                     target_url);
-            return;
+            self.state.insert(target_url.clone(), Target::new(target_url));
         }
-        self.state.insert(target_url, reachable);
     }
 
-    pub fn reachable(&mut self, target_url: String) {
-        info!("Target [{}] is now reachable.", target_url);
-        self.update(target_url, true);
+    pub fn remove_target(&mut self, target_url: &str) {
+        self.state.remove(target_url);
     }
 
-    pub fn unreachable(&mut self, target_url: String) {
-        info!("Target [{}] is now unreachable.", target_url);
-        self.update(target_url, false);
+    /// Records the state of the long-lived connection to the target.
+    pub fn record_heartbeat(&mut self, target_url: &str, flow: &Flow) {
+        if let Some(target) = self.state.get_mut(target_url) {
+            target.heartbeat = Some(flow.clone());
+        }
+    }
+
+    /// Records the reachability of each address family of a hostname target
+    /// in the most recent round of probes.
+    pub fn record_families(&mut self,
+                           target_url: &str,
+                           families: Vec<FamilyStatus>) {
+        if let Some(target) = self.state.get_mut(target_url) {
+            target.families = families;
+        }
+    }
+
+    /// Records the result of a throughput test, replacing any earlier result
+    /// in the same direction.
+    pub fn record_throughput(&mut self,
+                             target_url: &str,
+                             result: &Throughput) {
+        if let Some(target) = self.state.get_mut(target_url) {
+            target.throughput.retain(|t| t.direction != result.direction);
+            target.throughput.push(result.clone());
+        }
+    }
+
+    /// Records the outcome of the most recent probe of the target.
+    pub fn record(&mut self, target_url: String, outcome: &Outcome) {
+        self.record_round(target_url, &[outcome.clone()]);
+    }
+
+    /// Records the outcomes of the attempts of the most recent round of
+    /// probes of the target.  The failure reported is that of the last
+    /// failed attempt.
+    pub fn record_round(&mut self, target_url: String, outcomes: &[Outcome]) {
+        let target = match self.state.get_mut(&target_url) {
+            Some(target) => target,
+            None => {
+                warn!("Received update state for unknown target [{}]",
+                         target_url);
+                return;
+            },
+        };
+
+        let reachability = Reachability::of(outcomes);
+        let loss_pct = loss_pct(outcomes);
+        let failure = last_failure(outcomes);
+        match failure {
+            None => info!("Target [{}] is now reachable.", target_url),
+            Some(ref failure) =>
+                info!("Target [{}] is now {} ({}% loss, {}: {}).",
+                      target_url,
+                      reachability.name(),
+                      loss_pct,
+                      failure.reason(),
+                      failure.message()),
+        }
+
+        target.reachable = reachability != Reachability::Unreachable;
+        target.reachability = reachability;
+        target.loss_pct = loss_pct;
+        target.failure = failure;
+        target.rtt_ms = mean_rtt_ms(outcomes);
+        target.observed_address = outcomes.iter().rev()
+            .filter_map(|o| o.observed_address.clone())
+            .next();
+        target.source = outcomes.iter().rev()
+            .filter_map(|o| o.source.clone())
+            .next();
+        target.resolved_address = outcomes.iter().rev()
+            .filter_map(|o| o.resolved_address)
+            .next();
+        target.timing = outcomes.iter().rev()
+            .filter_map(|o| o.timing.clone())
+            .next();
+        target.path_mtu = outcomes.iter().rev()
+            .filter_map(|o| o.path_mtu)
+            .next();
+        target.responder = outcomes.iter().rev()
+            .filter_map(|o| o.responder.clone())
+            .next();
     }
 }
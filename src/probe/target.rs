@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+
+use common::dns;
+use common::net::{self, split_host_port, unbracket_host, Family, Resolution,
+    Source};
+use probe::protocol::mtu::{IPV4_MIN_MTU, IPV6_MIN_MTU};
+
+use regex::Regex;
+use rustc_serialize::{json, Decodable, Decoder};
+
+/// A target to probe, identified by its URL, e.g: `http://a:9000/status`
+/// or `tcp://b:6379`.  The URL scheme selects the prober; the remaining
+/// fields are optional settings for particular probers.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct TargetSpec {
+    pub url: String,
+    /// Time to wait for the target to respond, overriding the prober's
+    /// default.
+    pub timeout_ms: Option<u64>,
+    pub tcp: Option<TcpOptions>,
+    pub http: Option<HttpOptions>,
+    pub identity: Option<IdentityOptions>,
+    pub heartbeat: Option<HeartbeatOptions>,
+    pub mtu: Option<MtuOptions>,
+    /// Probe only the addresses of this family.  Without it, the addresses
+    /// of each family a hostname resolves to are probed separately.
+    pub family: Option<Family>,
+    /// The local address and/or network device to probe from, overriding
+    /// the prober's default.
+    pub source: Option<Source>,
+    /// Addresses to probe instead of those the host of the URL resolves to,
+    /// like the `--resolve` option of curl.  The host is still sent as the
+    /// HTTP `Host` header and TLS server name.
+    pub resolve: Option<Vec<String>>,
+    /// The nameserver to resolve the host of the URL with, e.g. `10.0.0.2`
+    /// or `10.0.0.2:5353`, overriding the prober's default.
+    pub dns_server: Option<String>,
+}
+
+/// Settings for `mtu://` targets.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct MtuOptions {
+    /// The smallest acceptable path MTU; a smaller one is reported as a
+    /// mismatch.
+    pub expect_min: Option<u32>,
+    /// The largest path MTU to try [default: the MTU of the local route].
+    pub max: Option<u32>,
+}
+
+/// Keeps a long-lived connection to a `tcp://` or `http://` target and
+/// sends heartbeats over it, to detect established flows being cut.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct HeartbeatOptions {
+    /// Milliseconds between heartbeats [default: 1000].
+    pub interval_ms: Option<u64>,
+    /// For `tcp://` targets, the bytes to send as a heartbeat
+    /// [default: "star\n"].
+    pub send: Option<String>,
+    /// For `tcp://` targets, the expected reply to a heartbeat.  Without
+    /// it, any reply will do.
+    pub expect: Option<Expect>,
+}
+
+/// The identity expected to answer a `star://` target.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct IdentityOptions {
+    pub id: Option<String>,
+    pub hostname: Option<String>,
+}
+
+/// Request and expected response for `http://` and `https://` targets.
+/// Without an expected status or body, any HTTP response is reachable.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct HttpOptions {
+    /// Request method [default: GET].
+    pub method: Option<String>,
+    /// Extra request headers, which may override the `Host` header.
+    pub headers: Option<BTreeMap<String, String>>,
+    pub body: Option<String>,
+    pub expect_status: Option<Vec<u16>>,
+    pub expect_body: Option<Expect>,
+}
+
+/// A script for `tcp://` targets: bytes to send once connected, and the
+/// response expected from the target.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct TcpOptions {
+    pub send: Option<String>,
+    pub expect: Option<Expect>,
+}
+
+/// An expectation about text received from a target.  Every supplied
+/// condition must hold.
+#[derive(Clone, Debug, PartialEq, RustcDecodable)]
+pub struct Expect {
+    pub prefix: Option<String>,
+    pub contains: Option<String>,
+    pub regex: Option<Pattern>,
+}
+
+impl Expect {
+    pub fn matches(&self, text: &str) -> bool {
+        if let Some(ref prefix) = self.prefix {
+            if !text.starts_with(prefix) { return false; }
+        }
+        if let Some(ref contains) = self.contains {
+            if !text.contains(contains) { return false; }
+        }
+        if let Some(ref regex) = self.regex {
+            if !regex.is_match(text) { return false; }
+        }
+        true
+    }
+
+    /// Whether receiving more text could still satisfy the expectation.
+    pub fn may_match_later(&self, text: &str) -> bool {
+        match self.prefix {
+            Some(ref prefix) if text.len() >= prefix.len() =>
+                text.starts_with(prefix),
+            Some(ref prefix) => prefix.starts_with(text),
+            None => true,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut conditions = vec!();
+        if let Some(ref prefix) = self.prefix {
+            conditions.push(format!("prefix {:?}", prefix));
+        }
+        if let Some(ref contains) = self.contains {
+            conditions.push(format!("containing {:?}", contains));
+        }
+        if let Some(ref regex) = self.regex {
+            conditions.push(format!("matching /{}/", regex.as_str()));
+        }
+        conditions.join(", ")
+    }
+}
+
+/// A regular expression, compiled when targets are loaded so that an
+/// invalid one is rejected up front.
+#[derive(Clone, Debug)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(raw: &str) -> Result<Pattern, String> {
+        Regex::new(raw)
+            .map(Pattern)
+            .map_err(|e| format!("Invalid regex [{}]: {}", raw, e))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Decodable for Pattern {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Pattern, D::Error> {
+        let raw: String = try!(Decodable::decode(d));
+        Pattern::new(&raw).map_err(|cause| d.error(&cause))
+    }
+}
+
+impl TargetSpec {
+    pub fn new(url: &str) -> TargetSpec {
+        TargetSpec {
+            url: url.to_string(),
+            timeout_ms: None,
+            tcp: None,
+            http: None,
+            identity: None,
+            heartbeat: None,
+            mtu: None,
+            family: None,
+            source: None,
+            resolve: None,
+            dns_server: None,
+        }
+    }
+
+    /// A copy of this target with a different URL, e.g. for an endpoint
+    /// discovered from this target's URL.  The addresses that override the
+    /// resolution of the original host do not apply to the new one.
+    pub fn with_url(&self, url: String) -> TargetSpec {
+        TargetSpec { url: url, resolve: None, ..self.clone() }
+    }
+
+    /// A copy of this target limited to the addresses of the family.
+    pub fn with_family(&self, family: Family) -> TargetSpec {
+        TargetSpec { family: Some(family), ..self.clone() }
+    }
+
+    pub fn scheme(&self) -> Option<&str> {
+        self.url.find("://").map(|index| &self.url[..index])
+    }
+
+    /// The `host[:port]` part of the URL.
+    pub fn authority(&self) -> Option<&str> {
+        self.url.find("://").map(|index| {
+            let rest = &self.url[index + 3..];
+            let end = rest.find(|c| c == '/' || c == '?').unwrap_or(rest.len());
+            &rest[..end]
+        })
+    }
+
+    /// A copy of this target probing from the source unless it has a
+    /// source of its own.
+    pub fn with_default_source(&self, source: &Source) -> TargetSpec {
+        if self.source.is_some() || source.is_any() {
+            return self.clone();
+        }
+        TargetSpec { source: Some(source.clone()), ..self.clone() }
+    }
+
+    /// The source to probe from, which is any source unless set.
+    pub fn source(&self) -> Source {
+        self.source.clone().unwrap_or(Source::default())
+    }
+
+    /// A copy of this target resolved with the nameserver unless it has a
+    /// nameserver of its own.
+    pub fn with_default_dns_server(&self, nameserver: &SocketAddr)
+                                   -> TargetSpec {
+        if self.dns_server.is_some() {
+            return self.clone();
+        }
+        TargetSpec {
+            dns_server: Some(format!("{}", nameserver)),
+            ..self.clone()
+        }
+    }
+
+    /// How to resolve the host of the URL: to the `resolve` addresses if
+    /// any, else with the `dns_server` if one is set, else with the system
+    /// resolver.
+    pub fn resolution(&self) -> Result<Resolution, String> {
+        let mut addresses = vec!();
+        for address in self.resolve.iter().flat_map(|a| a.iter()) {
+            match unbracket_host(address).parse::<IpAddr>() {
+                Ok(ip) => addresses.push(ip),
+                Err(_) => return Err(format!(
+                    "Invalid address [{}] to resolve [{}] to",
+                    address, self.url)),
+            }
+        }
+        let nameserver = try!(self.nameserver());
+        Ok(Resolution { addresses: addresses, nameserver: nameserver, })
+    }
+
+    /// The nameserver to resolve the host of the URL with, if one is set.
+    pub fn nameserver(&self) -> Result<Option<SocketAddr>, String> {
+        match self.dns_server {
+            Some(ref address) => dns::parse_nameserver(address).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The host of the URL, without the brackets of IPv6 literals.
+    pub fn host(&self) -> Result<String, String> {
+        match self.authority().map(split_host_port) {
+            Some(Ok((ref host, _))) if !host.is_empty() => Ok(host.clone()),
+            Some(Err(cause)) => Err(format!("{} in [{}]", cause, self.url)),
+            _ => Err(format!("Invalid target URL [{}]", self.url)),
+        }
+    }
+
+    /// The host and port of the URL, which must include a port unless the
+    /// scheme is `http` or `https`.  IPv6 literals must be bracketed, e.g:
+    /// `tcp://[fd00::1]:6379`.
+    pub fn host_port(&self) -> Result<(String, u16), String> {
+        let host = try!(self.host());
+        match try!(split_host_port(self.authority().unwrap_or(""))) {
+            (_, Some(port)) => Ok((host, port)),
+            (_, None) => match self.scheme() {
+                Some("http") => Ok((host, 80)),
+                Some("https") => Ok((host, 443)),
+                _ => Err(format!("Missing port in [{}]", self.url)),
+            },
+        }
+    }
+
+    /// The path and query of the URL, e.g. `/status`.
+    pub fn path(&self) -> &str {
+        match self.url.find("://") {
+            Some(index) => {
+                let rest = &self.url[index + 3..];
+                match rest.find(|c| c == '/' || c == '?') {
+                    Some(start) if rest[start..].starts_with("/") =>
+                        &rest[start..],
+                    _ => "/",
+                }
+            },
+            None => "/",
+        }
+    }
+
+    /// Resolves the host and port of the URL to socket addresses, of the
+    /// target's family if it has one.
+    pub fn socket_addrs(&self) -> Result<Vec<SocketAddr>, String> {
+        let (host, port) = try!(self.host_port());
+        let resolution = try!(self.resolution());
+        net::resolve(&host, port, self.family, &resolution)
+            .map_err(|e| format!("Failed to resolve [{}]: {}", host, e))
+    }
+
+    /// Resolves the host of the URL to IP addresses, of the target's family
+    /// if it has one.  Unlike `socket_addrs`, the URL needs no port.
+    pub fn ip_addrs(&self) -> Result<Vec<IpAddr>, String> {
+        let host = try!(self.host());
+        let resolution = try!(self.resolution());
+        net::resolve(&host, 0, self.family, &resolution)
+            .map(|addrs| addrs.into_iter().map(|addr| addr.ip()).collect())
+            .map_err(|e| format!("Failed to resolve [{}]: {}", host, e))
+    }
+
+    /// Checks the settings that only apply to particular schemes.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.heartbeat.is_some() {
+            match self.scheme() {
+                Some("tcp") | Some("http") => (),
+                _ => return Err(format!(
+                    "Heartbeats need a tcp:// or http:// target, not [{}]",
+                    self.url)),
+            }
+        }
+        if let Some(ref mtu) = self.mtu {
+            // Without a family, the host may resolve to either.
+            let min_mtu = match self.family {
+                Some(Family::Ipv6) => IPV6_MIN_MTU,
+                _ => IPV4_MIN_MTU,
+            };
+            for (name, value) in vec!(("max", mtu.max),
+                                      ("expect_min", mtu.expect_min)) {
+                match value {
+                    Some(value) if value < min_mtu => return Err(format!(
+                        "mtu.{} of [{}] is below the minimum MTU of {}",
+                        name, self.url, min_mtu)),
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a JSON array of targets from the file at the supplied path.
+pub fn read_targets_file(path: &str) -> Result<Vec<TargetSpec>, String> {
+    let mut raw = String::new();
+    let read_result = File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut raw));
+    if let Err(cause) = read_result {
+        return Err(format!("Failed to read file [{}]!\n{}", path, cause));
+    }
+
+    let targets: Vec<TargetSpec> = try!(json::decode(&raw).map_err(|cause|
+        format!("Failed to parse file [{}] as targets!\n{}", path, cause)));
+    for target in &targets {
+        try!(target.validate().map_err(|cause|
+            format!("Invalid target in file [{}]!\n{}", path, cause)));
+    }
+    Ok(targets)
+}
@@ -0,0 +1,268 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::http;
+use probe::protocol::elapsed_ms;
+use probe::target::TargetSpec;
+
+use hyper::Client;
+use hyper::client::Body;
+use hyper::header::Connection;
+use hyper::status::StatusCode;
+use time;
+
+/// Paths of the throughput endpoints served by every star-probe.
+pub const DOWNLOAD_PATH: &'static str = "/throughput/download";
+pub const UPLOAD_PATH: &'static str = "/throughput/upload";
+
+pub const DEFAULT_SECONDS: u64 = 10;
+/// Longest test a peer will serve or a probe will run.
+pub const MAX_SECONDS: u64 = 60;
+
+/// Most bytes a peer will accept or a probe will download in one test.
+pub const MAX_BYTES: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Most tests a star-probe will serve and run at once.
+pub const MAX_CONCURRENT_TESTS: usize = 2;
+
+/// Extra time allowed for a transfer beyond `MAX_SECONDS`, e.g. for the
+/// connection to be set up.
+const GRACE_SECONDS: u64 = 5;
+
+/// Size of the chunks of filler sent in either direction.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// From the peer to the probe.
+    Download,
+    /// From the probe to the peer.
+    Upload,
+}
+
+impl Direction {
+    pub fn parse(name: &str) -> Result<Direction, String> {
+        match name {
+            "download" => Ok(Direction::Download),
+            "upload" => Ok(Direction::Upload),
+            _ => Err(format!("Unknown direction [{}]; expected download or \
+                              upload",
+                             name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Direction::Download => "download",
+            Direction::Upload => "upload",
+        }
+    }
+}
+
+/// The result of a throughput test against a target.
+#[derive(Clone, Debug)]
+pub struct Throughput {
+    pub direction: Direction,
+    pub bytes: u64,
+    pub duration_ms: f64,
+    pub mbps: f64,
+    /// When the test finished, in seconds since the epoch.
+    pub measured_at: i64,
+}
+
+impl Throughput {
+    pub fn new(direction: Direction, bytes: u64, duration_ms: f64)
+               -> Throughput {
+        Throughput {
+            direction: direction,
+            bytes: bytes,
+            duration_ms: duration_ms,
+            mbps: mbps(bytes, duration_ms),
+            measured_at: time::get_time().sec,
+        }
+    }
+}
+
+/// Megabits per second.
+pub fn mbps(bytes: u64, duration_ms: f64) -> f64 {
+    if duration_ms <= 0.0 {
+        return 0.0;
+    }
+    (bytes as f64 * 8.0) / (duration_ms * 1000.0)
+}
+
+/// Clamps the requested duration of a test to `[1, MAX_SECONDS]`.
+pub fn clamp_seconds(seconds: u64) -> u64 {
+    cmp::max(1, cmp::min(seconds, MAX_SECONDS))
+}
+
+/// Runs a throughput test against a target that is another star-probe,
+/// transferring filler through its throughput endpoints for the given
+/// number of seconds.  Only `http://` and `https://` targets are supported;
+/// the endpoints are found at the root of the target's authority.
+pub fn measure(target: &TargetSpec, direction: Direction, seconds: u64)
+               -> Result<Throughput, String> {
+    let base_url = match (target.scheme(), target.authority()) {
+        (Some(scheme), Some(authority))
+                if scheme == "http" || scheme == "https" =>
+            format!("{}://{}", scheme, authority),
+        _ => return Err(format!("Throughput tests need an http:// or \
+                                 https:// star peer, not [{}]",
+                                target.url)),
+    };
+    let seconds = clamp_seconds(seconds);
+    let client = http::client_from(target.family,
+                                   target.source(),
+                                   try!(target.resolution()));
+
+    info!("Measuring {} throughput to [{}] for {} seconds",
+          direction.name(), target.url, seconds);
+    match direction {
+        Direction::Download => download(&client, &base_url, seconds),
+        Direction::Upload => upload(&client, &base_url, seconds),
+    }
+}
+
+fn download(client: &Client, base_url: &str, seconds: u64)
+            -> Result<Throughput, String> {
+    let url = format!("{}{}?seconds={}", base_url, DOWNLOAD_PATH, seconds);
+    let start_ns = time::precise_time_ns();
+
+    let mut response = try!(client.get(&url)
+        .header(Connection::close())
+        .send()
+        .map_err(|cause| format!("Failed to request [{}]: {}", url, cause)));
+    if response.status != StatusCode::Ok {
+        return Err(format!("[{}] returned [{}]", url, response.status));
+    }
+
+    let bytes = try!(drain(&mut response)
+        .map_err(|cause| format!("Download from [{}] failed: {}",
+                                 url, cause)));
+    Ok(Throughput::new(Direction::Download, bytes, elapsed_ms(start_ns)))
+}
+
+fn upload(client: &Client, base_url: &str, seconds: u64)
+          -> Result<Throughput, String> {
+    let url = format!("{}{}", base_url, UPLOAD_PATH);
+    let start_ns = time::precise_time_ns();
+
+    let mut filler = Filler::new(seconds);
+    let mut response = try!(client.post(&url)
+        .header(Connection::close())
+        .body(Body::ChunkedBody(&mut filler))
+        .send()
+        .map_err(|cause| format!("Failed to upload to [{}]: {}", url, cause)));
+    let duration_ms = elapsed_ms(start_ns);
+
+    // Drain the summary of the peer, which is not needed.
+    let mut body = String::new();
+    let _ = response.read_to_string(&mut body);
+    if response.status != StatusCode::Ok {
+        return Err(format!("[{}] returned [{}]: {}",
+                           url, response.status, body));
+    }
+
+    Ok(Throughput::new(Direction::Upload, filler.bytes, duration_ms))
+}
+
+/// Streams filler to the writer for the given number of seconds and
+/// returns the number of bytes written.  This serves the download
+/// endpoint.
+pub fn send_filler<W: Write>(writer: &mut W, seconds: u64) -> io::Result<u64> {
+    let mut filler = Filler::new(clamp_seconds(seconds));
+    let mut buffer = vec!(0; CHUNK_SIZE);
+    loop {
+        let n = try!(filler.read(&mut buffer));
+        if n == 0 {
+            return Ok(filler.bytes);
+        }
+        try!(writer.write_all(&buffer[..n]));
+    }
+}
+
+/// Reads the reader to the end and returns the number of bytes read.  This
+/// serves the upload endpoint.  Reading stops early once `MAX_BYTES` have
+/// been read or `MAX_SECONDS` have passed, with some grace.
+pub fn drain<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let deadline_ns = time::precise_time_ns() +
+        (MAX_SECONDS + GRACE_SECONDS) * 1_000_000_000;
+    let mut buffer = vec!(0; CHUNK_SIZE);
+    let mut bytes = 0;
+    loop {
+        if bytes >= MAX_BYTES || time::precise_time_ns() >= deadline_ns {
+            warn!("Stopped reading filler after {} bytes", bytes);
+            return Ok(bytes);
+        }
+        match try!(reader.read(&mut buffer)) {
+            0 => return Ok(bytes),
+            n => bytes += n as u64,
+        }
+    }
+}
+
+/// Limits the number of tests that run at once, e.g. the throughput tests
+/// served to peers and those run against them.
+#[derive(Clone)]
+pub struct TestLimiter {
+    running: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl TestLimiter {
+    pub fn new(max: usize) -> TestLimiter {
+        TestLimiter { running: Arc::new(AtomicUsize::new(0)), max: max, }
+    }
+
+    /// Starts a test, if fewer than the maximum are running.  The test
+    /// ends when the returned slot is dropped.
+    pub fn try_start(&self) -> Option<TestSlot> {
+        if self.running.fetch_add(1, Ordering::SeqCst) >= self.max {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(TestSlot { running: self.running.clone(), })
+    }
+}
+
+/// A running test.
+pub struct TestSlot {
+    running: Arc<AtomicUsize>,
+}
+
+impl Drop for TestSlot {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A reader of filler that ends after a number of seconds.
+struct Filler {
+    deadline_ns: u64,
+    bytes: u64,
+}
+
+impl Filler {
+    fn new(seconds: u64) -> Filler {
+        Filler {
+            deadline_ns: time::precise_time_ns() + seconds * 1_000_000_000,
+            bytes: 0,
+        }
+    }
+}
+
+impl Read for Filler {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if time::precise_time_ns() >= self.deadline_ns {
+            return Ok(0);
+        }
+        let n = cmp::min(buf.len(), CHUNK_SIZE);
+        for byte in buf[..n].iter_mut() {
+            *byte = b'*';
+        }
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,242 @@
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use common::net::{self, Source};
+use common::sockopt::set_int_option;
+use probe::target::TargetSpec;
+
+use libc;
+use time;
+
+// Linux socket options and constants of the socket error queue.
+const IP_TTL: libc::c_int = 2;
+const IP_RECVERR: libc::c_int = 11;
+const IPV6_UNICAST_HOPS: libc::c_int = 16;
+const IPV6_RECVERR: libc::c_int = 25;
+const MSG_ERRQUEUE: libc::c_int = 0x2000;
+const SO_EE_ORIGIN_ICMP: u8 = 2;
+const SO_EE_ORIGIN_ICMP6: u8 = 3;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+const ICMPV6_TIME_EXCEEDED: u8 = 3;
+
+/// The first destination port of the datagrams, as used by traceroute.
+const BASE_PORT: u16 = 33434;
+
+pub const DEFAULT_MAX_HOPS: u8 = 30;
+/// Most hops a trace may be asked to try.
+pub const MAX_HOPS: u8 = 64;
+pub const DEFAULT_HOP_TIMEOUT_MS: u64 = 1000;
+
+/// Most traces a star-probe will run at once.
+pub const MAX_CONCURRENT_TRACES: usize = 2;
+
+/// One hop along the path to a target.
+#[derive(Clone, Debug)]
+pub struct Hop {
+    pub ttl: u8,
+    /// The address of the router or host that answered, if any did.
+    pub address: Option<String>,
+    pub rtt_ms: Option<f64>,
+    /// Whether the answer came from the destination itself.
+    pub destination: bool,
+    /// The type and code of the ICMP error that answered, if any.
+    pub icmp_type: Option<u8>,
+    pub icmp_code: Option<u8>,
+}
+
+/// The path to a target, up to the destination or the last hop tried.
+#[derive(Clone, Debug)]
+pub struct Trace {
+    pub url: String,
+    pub destination: String,
+    pub hops: Vec<Hop>,
+    pub reached: bool,
+}
+
+/// Traces the path to the target's host by sending UDP datagrams with
+/// increasing TTLs and reading the ICMP errors they cause from the socket
+/// error queue (`IP_RECVERR`), which needs no privileges.  Routers answer
+/// with time exceeded errors; the destination answers with port
+/// unreachable.  Any other error, e.g. a router reporting the destination
+/// unreachable, also ends the trace, without reaching the destination.
+/// Hops that do not answer within the timeout have no address.  The
+/// datagrams leave from the target's source.
+pub fn trace(target: &TargetSpec, max_hops: u8, hop_timeout_ms: u64)
+             -> Result<Trace, String> {
+    let ip = match try!(target.ip_addrs()).first() {
+        Some(ip) => *ip,
+        None => return Err(format!("No addresses for [{}]", target.url)),
+    };
+
+    info!("Tracing the path to [{}] ({})", target.url, ip);
+    let mut hops = vec!();
+    let mut reached = false;
+    let source = target.source();
+    for ttl in 1..max_hops as u16 + 1 {
+        let (hop, ends_path) =
+            try!(probe_hop(ip, &source, ttl as u8, hop_timeout_ms)
+                .map_err(|cause| format!("Failed to trace [{}]: {}",
+                                         ip, cause)));
+        reached = hop.destination;
+        hops.push(hop);
+        if ends_path {
+            break;
+        }
+    }
+
+    Ok(Trace {
+        url: target.url.clone(),
+        destination: format!("{}", ip),
+        hops: hops,
+        reached: reached,
+    })
+}
+
+/// Probes a single hop, returning it along with whether its answer ends the
+/// path, i.e. is anything other than a time exceeded error.
+fn probe_hop(ip: IpAddr, source: &Source, ttl: u8, timeout_ms: u64)
+             -> io::Result<(Hop, bool)> {
+    let (level, ttl_option, recverr_option) = match ip {
+        IpAddr::V4(_) => (libc::IPPROTO_IP, IP_TTL, IP_RECVERR),
+        IpAddr::V6(_) => (libc::IPPROTO_IPV6, IPV6_UNICAST_HOPS, IPV6_RECVERR),
+    };
+    let dest = SocketAddr::new(ip, BASE_PORT + ttl as u16 - 1);
+    let socket = try!(net::udp_socket(&dest, source));
+    let fd = socket.as_raw_fd();
+    try!(set_int_option(fd, level, recverr_option, 1));
+    try!(set_int_option(fd, level, ttl_option, ttl as libc::c_int));
+
+    let start_ns = time::precise_time_ns();
+    try!(socket.send_to(b"star", dest));
+
+    let mut hop = Hop {
+        ttl: ttl,
+        address: None,
+        rtt_ms: None,
+        destination: false,
+        icmp_type: None,
+        icmp_code: None,
+    };
+
+    // Errors are reported as POLLERR, which poll always waits for.
+    let deadline_ns = start_ns + timeout_ms * 1_000_000;
+    loop {
+        let now_ns = time::precise_time_ns();
+        if now_ns >= deadline_ns {
+            return Ok((hop, false));
+        }
+        let mut pollfd = libc::pollfd { fd: fd, events: 0, revents: 0 };
+        let wait_ms = ((deadline_ns - now_ns) / 1_000_000 + 1) as libc::c_int;
+        let ready = unsafe { libc::poll(&mut pollfd, 1, wait_ms) };
+        if ready < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if ready == 0 || pollfd.revents & libc::POLLERR == 0 {
+            continue;
+        }
+
+        if let Some(error) = try!(read_error(fd)) {
+            if error.origin != SO_EE_ORIGIN_ICMP &&
+                error.origin != SO_EE_ORIGIN_ICMP6 {
+                continue;
+            }
+            let time_exceeded = if error.origin == SO_EE_ORIGIN_ICMP {
+                error.icmp_type == ICMP_TIME_EXCEEDED
+            } else {
+                error.icmp_type == ICMPV6_TIME_EXCEEDED
+            };
+            hop.address = Some(format!("{}", error.offender));
+            hop.rtt_ms = Some(
+                (time::precise_time_ns() - start_ns) as f64 / 1e6);
+            hop.destination = error.offender == ip && !time_exceeded;
+            hop.icmp_type = Some(error.icmp_type);
+            hop.icmp_code = Some(error.icmp_code);
+            return Ok((hop, !time_exceeded));
+        }
+    }
+}
+
+/// An error read from the socket error queue.
+struct QueuedError {
+    /// The sender of the ICMP error.
+    offender: IpAddr,
+    origin: u8,
+    icmp_type: u8,
+    icmp_code: u8,
+}
+
+/// Reads an error from the socket error queue.
+fn read_error(fd: RawFd) -> io::Result<Option<QueuedError>> {
+    let mut data = [0u8; 512];
+    let mut control = [0u8; 512];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    let result = unsafe { libc::recvmsg(fd, &mut msg, MSG_ERRQUEUE) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Walk the control messages for a `sock_extended_err`, which is
+    // followed by the address of the offender.
+    let header_len = mem::size_of::<libc::cmsghdr>();
+    let align = mem::size_of::<usize>();
+    let control_len = msg.msg_controllen as usize;
+    let mut offset = 0;
+    while offset + header_len <= control_len {
+        let cmsg: libc::cmsghdr = unsafe {
+            ::std::ptr::read_unaligned(
+                control[offset..].as_ptr() as *const libc::cmsghdr)
+        };
+        let cmsg_len = cmsg.cmsg_len as usize;
+        if cmsg_len < header_len || offset + cmsg_len > control_len {
+            break;
+        }
+        let body = &control[offset + header_len..offset + cmsg_len];
+        let is_error =
+            (cmsg.cmsg_level == libc::IPPROTO_IP &&
+             cmsg.cmsg_type == IP_RECVERR) ||
+            (cmsg.cmsg_level == libc::IPPROTO_IPV6 &&
+             cmsg.cmsg_type == IPV6_RECVERR);
+        if is_error && body.len() >= 16 {
+            if let Some(offender) = read_offender(&body[16..]) {
+                return Ok(Some(QueuedError {
+                    offender: offender,
+                    origin: body[4],
+                    icmp_type: body[5],
+                    icmp_code: body[6],
+                }));
+            }
+        }
+        offset += (cmsg_len + align - 1) / align * align;
+    }
+    Ok(None)
+}
+
+/// Reads a `sockaddr_in` or `sockaddr_in6`.
+fn read_offender(addr: &[u8]) -> Option<IpAddr> {
+    if addr.len() < 2 {
+        return None;
+    }
+    let family = unsafe {
+        ::std::ptr::read_unaligned(addr.as_ptr() as *const libc::sa_family_t)
+    } as libc::c_int;
+    if family == libc::AF_INET && addr.len() >= 8 {
+        Some(IpAddr::V4(Ipv4Addr::new(addr[4], addr[5], addr[6], addr[7])))
+    } else if family == libc::AF_INET6 && addr.len() >= 24 {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&addr[8..24]);
+        Some(IpAddr::V6(Ipv6Addr::from(octets)))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,32 @@
+use target::Listener;
+
+use jsonway::{ObjectBuilder, ObjectSerializer};
+
+pub struct ListenerSerializer;
+
+impl ObjectSerializer<Listener> for ListenerSerializer {
+    fn root(&self) -> Option<&str> { Some("listener") }
+    fn build(&self, listener: &Listener, json: &mut ObjectBuilder) {
+        json.set("protocol", format!("{}", listener.protocol));
+        json.set("address", listener.address.clone());
+        json.set("port", listener.port);
+        json.set("url", listener.url());
+        json.set("listening", listener.listening);
+        if let Some(ref error) = listener.error {
+            json.set("error", error.clone());
+        }
+        json.set("requests", listener.requests);
+    }
+}
+
+pub struct ListenersSerializer;
+
+impl ObjectSerializer<Vec<Listener>> for ListenersSerializer {
+    fn root(&self) -> Option<&str> { None }
+    fn build(&self, listeners: &Vec<Listener>, json: &mut ObjectBuilder) {
+        let listeners_json = listeners.iter().map(|l|
+            ListenerSerializer.serialize(l, false)).collect::<Vec<_>>();
+
+        json.set("listeners", listeners_json);
+    }
+}
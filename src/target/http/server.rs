@@ -0,0 +1,95 @@
+use std::io::Write;
+use std::sync::{Arc, RwLock};
+
+use common::http::{self, query_param, split_query};
+use common::identity::{Identity, Whoami, WhoamiSerializer,
+    REMOTE_ADDRESS_HEADER};
+use common::net::canonical_ip;
+use target::Listeners;
+use target::http::json::ListenersSerializer;
+
+use hyper;
+use hyper::header::ContentType;
+use hyper::server::Request;
+use hyper::server::Response;
+use hyper::net::Fresh;
+use hyper::uri::RequestUri::AbsolutePath;
+use jsonway::{ObjectSerializer};
+
+pub fn start_server(listeners: Arc<RwLock<Listeners>>,
+                    identity: Identity,
+                    addresses: Vec<String>,
+                    port: u16) {
+    let listeners_handler = ListenersHandler {
+        listeners: listeners,
+        identity: identity,
+    };
+    http::serve(&addresses, port, move |req, res| {
+        listeners_handler.handle(req, res);
+    });
+}
+
+struct ListenersHandler {
+    listeners: Arc<RwLock<Listeners>>,
+    identity: Identity,
+}
+
+impl ListenersHandler {
+    fn handle(&self, req: Request, mut res: Response<Fresh>) {
+        info!("Request from [{:?}]: {:?} {:?}",
+              req.remote_addr,
+              req.method,
+              req.uri);
+
+        let remote_address =
+            format!("{}", canonical_ip(req.remote_addr.ip()));
+        res.headers_mut().set_raw(REMOTE_ADDRESS_HEADER,
+                                  vec!(remote_address.clone().into_bytes()));
+
+        match req.uri {
+            AbsolutePath(ref path) => {
+                let (path, query) = split_query(path);
+                match (&req.method, path) {
+                    (&hyper::Get, "/listeners") => {
+                        let listeners =
+                            self.listeners.read().unwrap().listeners();
+                        let listeners_json = ListenersSerializer
+                            .serialize(&listeners, true)
+                            .to_string();
+
+                        res.headers_mut().set(ContentType::json());
+
+                        let mut res = res.start().unwrap();
+                        res.write_all(listeners_json.as_bytes()).unwrap();
+                        res.end().unwrap();
+                    }
+                    (&hyper::Get, "/whoami") => {
+                        let whoami = Whoami {
+                            identity: self.identity.clone(),
+                            nonce: query_param(&query, "nonce")
+                                .map(|n| n.to_string()),
+                            remote_address: remote_address,
+                        };
+                        let whoami_json = WhoamiSerializer
+                            .serialize(&whoami, true)
+                            .to_string();
+
+                        res.headers_mut().set(ContentType::json());
+
+                        let mut res = res.start().unwrap();
+                        res.write_all(whoami_json.as_bytes()).unwrap();
+                        res.end().unwrap();
+                    }
+                    _ => {
+                        // Anything else is invalid.
+                        *res.status_mut() = hyper::NotFound;
+                        return;
+                    }
+                }
+            },
+            _ => {
+                return;
+            }
+        };
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+use common::net::join_host_port;
+
+pub mod http;
+pub mod responder;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Protocol::Tcp => write!(f, "tcp"),
+            Protocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// A port that star-target answers on.
+#[derive(Clone, Debug)]
+pub struct Listener {
+    pub protocol: Protocol,
+    pub address: String,
+    pub port: u16,
+    /// Whether the port was bound successfully.
+    pub listening: bool,
+    /// Why the port could not be bound, if it could not.
+    pub error: Option<String>,
+    /// Connections accepted (TCP) or datagrams answered (UDP).
+    pub requests: u64,
+}
+
+impl Listener {
+    /// The URL that probes can use to reach this listener.
+    pub fn url(&self) -> String {
+        format!("{}://{}",
+                self.protocol, join_host_port(&self.address, self.port))
+    }
+}
+
+/// The set of listeners of a star-target process.
+pub struct Listeners {
+    listeners: Vec<Listener>,
+}
+
+impl Listeners {
+    pub fn new() -> Listeners {
+        Listeners { listeners: vec!(), }
+    }
+
+    pub fn listeners(&self) -> Vec<Listener> {
+        self.listeners.clone()
+    }
+
+    pub fn add(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+        self.listeners.sort_by(|a, b|
+            (a.protocol, a.port).cmp(&(b.protocol, b.port)));
+    }
+
+    /// Counts a request answered by the listener for the protocol and port.
+    pub fn record(&mut self, protocol: Protocol, port: u16) {
+        for listener in self.listeners.iter_mut() {
+            if listener.protocol == protocol && listener.port == port {
+                listener.requests += 1;
+            }
+        }
+    }
+}
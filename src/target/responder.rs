@@ -0,0 +1,173 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use common::identity::{Identity, Whoami, WhoamiSerializer};
+use common::net::{canonical_ip, join_host_port};
+use probe::protocol::mtu::MTU_PROBE_PREFIX;
+use target::{Listener, Listeners, Protocol};
+
+use jsonway::ObjectSerializer;
+
+const IDLE_TIMEOUT_MS: u64 = 60 * 1000;
+
+/// Most ports a star-target will listen on, counting each protocol and
+/// address, as each listener holds a thread.
+pub const MAX_LISTENERS: usize = 1024;
+
+/// Starts an echo/identity responder on each of the supplied ports of each
+/// of the supplied addresses.
+///
+/// TCP responders greet each connection with a line of JSON in the form of
+/// `GET /whoami`, then echo everything they receive until the connection is
+/// closed.  UDP responders answer each datagram with the same line followed
+/// by the datagram's payload, except for path MTU probes, which are echoed
+/// back unchanged.  Ports that cannot be bound are recorded as not
+/// listening.  Fails without listening if that would take more than
+/// `MAX_LISTENERS` listeners.
+pub fn start_responders(identity: Identity,
+                        addresses: &Vec<String>,
+                        tcp_ports: &Vec<u16>,
+                        udp_ports: &Vec<u16>,
+                        listeners: Arc<RwLock<Listeners>>)
+                        -> Result<(), String> {
+    let count = addresses.len() * (tcp_ports.len() + udp_ports.len());
+    if count > MAX_LISTENERS {
+        return Err(format!("Listening on {} ports exceeds the limit of {}",
+                           count, MAX_LISTENERS));
+    }
+
+    for address in addresses {
+        for port in tcp_ports {
+            let bind_addr: &str = &join_host_port(address, *port);
+            let result = TcpListener::bind(bind_addr).map(|socket| {
+                let identity = identity.clone();
+                let listeners = listeners.clone();
+                let port = *port;
+                thread::spawn(move ||
+                    serve_tcp(socket, port, identity, listeners));
+            });
+            add_listener(&listeners, Protocol::Tcp, address, *port, result);
+        }
+
+        for port in udp_ports {
+            let bind_addr: &str = &join_host_port(address, *port);
+            let result = UdpSocket::bind(bind_addr).map(|socket| {
+                let identity = identity.clone();
+                let listeners = listeners.clone();
+                let port = *port;
+                thread::spawn(move ||
+                    serve_udp(socket, port, identity, listeners));
+            });
+            add_listener(&listeners, Protocol::Udp, address, *port, result);
+        }
+    }
+    Ok(())
+}
+
+fn add_listener<E: fmt::Display>(listeners: &Arc<RwLock<Listeners>>,
+                                 protocol: Protocol,
+                                 address: &str,
+                                 port: u16,
+                                 result: Result<(), E>) {
+    let error = match result {
+        Ok(()) => {
+            info!("Listening on [{}://{}]",
+                  protocol, join_host_port(address, port));
+            None
+        },
+        Err(cause) => {
+            error!("Failed to listen on [{}://{}]: {}",
+                   protocol, join_host_port(address, port), cause);
+            Some(format!("{}", cause))
+        },
+    };
+    listeners.write().unwrap().add(Listener {
+        protocol: protocol,
+        address: address.to_string(),
+        port: port,
+        listening: error.is_none(),
+        error: error,
+        requests: 0,
+    });
+}
+
+fn serve_tcp(socket: TcpListener,
+             port: u16,
+             identity: Identity,
+             listeners: Arc<RwLock<Listeners>>) {
+    for stream in socket.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(cause) => {
+                warn!("Failed to accept connection on port [{}]: {}",
+                      port, cause);
+                continue;
+            },
+        };
+        listeners.write().unwrap().record(Protocol::Tcp, port);
+        let identity = identity.clone();
+        thread::spawn(move || {
+            if let Err(cause) = echo(stream, &identity) {
+                warn!("Connection on port [{}] failed: {}", port, cause);
+            }
+        });
+    }
+}
+
+fn echo(mut stream: TcpStream, identity: &Identity) -> io::Result<()> {
+    let remote_addr = try!(stream.peer_addr());
+    info!("Connection from [{}] on port [{}]",
+          remote_addr, try!(stream.local_addr()).port());
+    try!(stream.set_read_timeout(
+        Some(Duration::from_millis(IDLE_TIMEOUT_MS))));
+    try!(stream.write_all(greeting(identity, &remote_addr).as_bytes()));
+
+    let mut buf = [0u8; 4096];
+    loop {
+        match try!(stream.read(&mut buf)) {
+            0 => return Ok(()),
+            n => try!(stream.write_all(&buf[..n])),
+        }
+    }
+}
+
+fn serve_udp(socket: UdpSocket,
+             port: u16,
+             identity: Identity,
+             listeners: Arc<RwLock<Listeners>>) {
+    let mut buf = [0u8; 65536];
+    loop {
+        let (n, remote_addr) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(cause) => {
+                warn!("Failed to receive on port [{}]: {}", port, cause);
+                continue;
+            },
+        };
+        listeners.write().unwrap().record(Protocol::Udp, port);
+
+        let mut reply = vec!();
+        if !buf[..n].starts_with(MTU_PROBE_PREFIX) {
+            reply.extend(greeting(&identity, &remote_addr).into_bytes());
+        }
+        reply.extend(buf[..n].iter().cloned());
+        if let Err(cause) = socket.send_to(&reply, remote_addr) {
+            warn!("Failed to reply to [{}] on port [{}]: {}",
+                  remote_addr, port, cause);
+        }
+    }
+}
+
+/// A line identifying this process and the client address it observed.
+fn greeting(identity: &Identity, remote_addr: &SocketAddr) -> String {
+    let whoami = Whoami {
+        identity: identity.clone(),
+        nonce: None,
+        remote_address: format!("{}", canonical_ip(remote_addr.ip())),
+    };
+    format!("{}\n", WhoamiSerializer.serialize(&whoami, true).to_string())
+}